@@ -0,0 +1,1039 @@
+use crate::error::ServerError;
+use crate::{AggregateOps, BinaryOps, CompareOps, PredicateOps, UnaryOps};
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::num::NonZeroU64;
+use std::str::FromStr;
+
+use bigdecimal::num_bigint::BigInt;
+use bigdecimal::{BigDecimal, Context, Signed, ToPrimitive, Zero};
+use serde_json::Value;
+
+/// The rounding mode applied to arithmetic results which exceed the precision of
+/// [`MathContext`].
+///
+/// [`MathContext`]: crate::MathContext
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum RoundingMode {
+    /// round towards the nearest neighbour, ties towards the even neighbour.
+    HalfEven,
+    /// round towards the nearest neighbour, ties away from zero.
+    HalfUp,
+    /// round towards negative infinity.
+    Floor,
+    /// round towards positive infinity.
+    Ceiling,
+    /// round towards zero.
+    Down,
+    /// round away from zero.
+    Up,
+}
+
+// `#[default]` on enum variant requires rustc 1.62.
+#[allow(clippy::derivable_impls)]
+impl Default for RoundingMode {
+    fn default() -> Self {
+        RoundingMode::HalfEven
+    }
+}
+
+impl From<RoundingMode> for bigdecimal::RoundingMode {
+    fn from(mode: RoundingMode) -> Self {
+        match mode {
+            RoundingMode::HalfEven => bigdecimal::RoundingMode::HalfEven,
+            RoundingMode::HalfUp => bigdecimal::RoundingMode::HalfUp,
+            RoundingMode::Floor => bigdecimal::RoundingMode::Floor,
+            RoundingMode::Ceiling => bigdecimal::RoundingMode::Ceiling,
+            RoundingMode::Down => bigdecimal::RoundingMode::Down,
+            RoundingMode::Up => bigdecimal::RoundingMode::Up,
+        }
+    }
+}
+
+impl FromStr for RoundingMode {
+    type Err = ServerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "half_even" => Ok(RoundingMode::HalfEven),
+            "half_up" => Ok(RoundingMode::HalfUp),
+            "floor" => Ok(RoundingMode::Floor),
+            "ceiling" => Ok(RoundingMode::Ceiling),
+            "down" => Ok(RoundingMode::Down),
+            "up" => Ok(RoundingMode::Up),
+            other => Err(ServerError::InvalidOption {
+                name: "rounding".into(),
+                value: other.into(),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for RoundingMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            RoundingMode::HalfEven => "half_even",
+            RoundingMode::HalfUp => "half_up",
+            RoundingMode::Floor => "floor",
+            RoundingMode::Ceiling => "ceiling",
+            RoundingMode::Down => "down",
+            RoundingMode::Up => "up",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// The precision and rounding mode applied to results of [`BinaryOps`], [`UnaryOps`] and values
+/// stored in [`UserDatabase`].
+///
+/// NOTE:
+///     - results are kept exact if `precision` is `None`, except that quotients, powers, square
+///     roots and transcendental functions are computed with 100 significant digits.
+///     - a request overrides the default context of [`UserDatabase`] with `"precision=<digits>"`
+///     and `"rounding=<mode>"` option params, e.g. `["x", "3", "precision=20", "rounding=down"]`.
+///     - `precision` is at most [`MathContext::MAX_PRECISION`] digits, the option param beyond
+///     it is rejected and a larger field value is capped.
+///
+/// [`BinaryOps`]: crate::BinaryOps
+/// [`UnaryOps`]: crate::UnaryOps
+/// [`UserDatabase`]: crate::database::UserDatabase
+/// [`MathContext::MAX_PRECISION`]: crate::MathContext::MAX_PRECISION
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub struct MathContext {
+    /// the maximum number of significant digits.
+    pub precision: Option<NonZeroU64>,
+    /// the rounding mode applied to digits beyond `precision`.
+    pub rounding: RoundingMode,
+}
+
+impl MathContext {
+    /// the maximum number of significant digits of `precision`.
+    pub const MAX_PRECISION: u64 = 500;
+
+    /// create a context rounding to `precision` significant digits by `rounding` mode.
+    pub fn new(precision: NonZeroU64, rounding: RoundingMode) -> Self {
+        MathContext {
+            precision: Some(precision),
+            rounding,
+        }
+    }
+
+    /// round `value` to the precision of context, the value is returned as it is if it fits.
+    pub fn round(&self, value: BigDecimal) -> BigDecimal {
+        match self.capped_precision().and_then(NonZeroU64::new) {
+            Some(precision) if value.digits() > precision.get() => {
+                let rounded = value.with_precision_round(precision, self.rounding.into());
+                // a carry out of the leading digit, e.g. `0.9995` rounded up to `1.000`, leaves
+                // one more digit than `precision`, which is a trailing zero.
+                if rounded.digits() > precision.get() {
+                    rounded.with_precision_round(precision, self.rounding.into())
+                } else {
+                    rounded
+                }
+            }
+            _ => value,
+        }
+    }
+
+    /// override the context with option param `name=value`, return
+    /// [`ServerError::InvalidOption`] if the option is unknown or its value is invalid.
+    ///
+    /// [`ServerError::InvalidOption`]: crate::error::ServerError::InvalidOption
+    pub(crate) fn set_option(&mut self, name: &str, value: &str) -> Result<(), ServerError> {
+        match name {
+            "precision" => match value.parse::<NonZeroU64>() {
+                Ok(precision) if precision.get() <= Self::MAX_PRECISION => {
+                    self.precision = Some(precision)
+                }
+                // the precision is bounded to keep computation time of a request bounded.
+                _ => {
+                    return Err(ServerError::InvalidOption {
+                        name: name.into(),
+                        value: value.into(),
+                    })
+                }
+            },
+            "rounding" => self.rounding = value.parse()?,
+            _ => {
+                return Err(ServerError::InvalidOption {
+                    name: name.into(),
+                    value: value.into(),
+                })
+            }
+        }
+
+        Ok(())
+    }
+
+    /// convert into [bigdecimal] context, which defaults to 100 significant digits if
+    /// `precision` is `None`.
+    ///
+    /// [bigdecimal]: https://docs.rs/bigdecimal/latest/bigdecimal/
+    fn to_context(self) -> Context {
+        let ctx = Context::default().with_rounding_mode(self.rounding.into());
+        match self.capped_precision().and_then(NonZeroU64::new) {
+            Some(precision) => ctx.with_precision(precision),
+            None => ctx,
+        }
+    }
+
+    /// the context rounding to 100 significant digits if `precision` is `None`, which is used
+    /// by inexact quotients and results of transcendental functions.
+    fn bounded(self) -> MathContext {
+        MathContext {
+            precision: Some(self.to_context().precision()),
+            ..self
+        }
+    }
+
+    /// the context of intermediate results with `digits` significant digits.
+    fn working(digits: u64) -> MathContext {
+        MathContext {
+            precision: NonZeroU64::new(digits),
+            rounding: RoundingMode::HalfEven,
+        }
+    }
+
+    /// compute `lhs / rhs` rounded to the precision of context, `rhs` MUST NOT be zero, return
+    /// [`ServerError::ArithmeticDomain`] if the exponent of quotient overflows.
+    ///
+    /// [`ServerError::ArithmeticDomain`]: crate::error::ServerError::ArithmeticDomain
+    fn divide(&self, lhs: &BigDecimal, rhs: &BigDecimal) -> Result<BigDecimal, ServerError> {
+        // inexact quotients are rounded to 100 significant digits if `precision` is `None`.
+        let ctx = self.bounded();
+        let precision = ctx.capped_precision().unwrap_or(0) as i64;
+        let (numerator, lhs_scale) = lhs.as_bigint_and_exponent();
+        let (denominator, rhs_scale) = rhs.as_bigint_and_exponent();
+
+        // shift the numerator to keep 2 guard digits beyond precision in the integer quotient.
+        let quotient_digits = (lhs.digits() as i64).saturating_sub(rhs.digits() as i64);
+        let mut shift = (precision + 2).saturating_sub(quotient_digits).max(0) + 1;
+        let overflow = || {
+            ServerError::ArithmeticDomain(
+                format!("quotient of {lhs} / {rhs} is out of range").into(),
+            )
+        };
+        let power = u32::try_from(shift).map_err(|_| overflow())?;
+        let numerator = numerator * BigInt::from(10).pow(power);
+        let mut quotient = &numerator / &denominator;
+        if (numerator % &denominator).is_zero() {
+            // remove the trailing zeros introduced by shifting from exact quotient.
+            let ten = BigInt::from(10);
+            while shift > 0 && (&quotient % &ten).is_zero() {
+                quotient /= &ten;
+                shift -= 1;
+            }
+        } else {
+            // append a sticky digit to inexact quotient so that it's not mistaken for a tie.
+            let sticky = if lhs.is_negative() != rhs.is_negative() {
+                -1
+            } else {
+                1
+            };
+            quotient = quotient * 10 + sticky;
+            shift += 1;
+        }
+        let scale = lhs_scale
+            .checked_sub(rhs_scale)
+            .and_then(|scale| scale.checked_add(shift))
+            .ok_or_else(overflow)?;
+
+        Ok(ctx.round(BigDecimal::new(quotient, scale)))
+    }
+
+    /// the precision of context capped at [`MathContext::MAX_PRECISION`].
+    ///
+    /// [`MathContext::MAX_PRECISION`]: crate::MathContext::MAX_PRECISION
+    fn capped_precision(&self) -> Option<u64> {
+        self.precision
+            .map(|precision| precision.get().min(Self::MAX_PRECISION))
+    }
+}
+
+impl BinaryOps {
+    /// compute `lhs op rhs` rounded by `ctx`, return [`ServerError::DivisionByZero`] if `rhs` is
+    /// zero divisor, or [`ServerError::ArithmeticDomain`] if the operands of `add`, `subtract`
+    /// or `modulo` differ in scale by more than 100000 digits.
+    ///
+    /// [`ServerError::DivisionByZero`]: crate::error::ServerError::DivisionByZero
+    /// [`ServerError::ArithmeticDomain`]: crate::error::ServerError::ArithmeticDomain
+    pub(crate) fn apply(
+        &self,
+        lhs: &BigDecimal,
+        rhs: &BigDecimal,
+        ctx: &MathContext,
+    ) -> Result<BigDecimal, ServerError> {
+        let res = match self {
+            BinaryOps::Add => {
+                check_scale_gap(self, lhs, rhs)?;
+                lhs + rhs
+            }
+            BinaryOps::Subtract => {
+                check_scale_gap(self, lhs, rhs)?;
+                lhs - rhs
+            }
+            BinaryOps::Multiply => lhs * rhs,
+            BinaryOps::Divide => {
+                if rhs.is_zero() {
+                    return Err(ServerError::DivisionByZero);
+                }
+                ctx.divide(lhs, rhs)?
+            }
+            BinaryOps::Pow => {
+                let exponent = match integer_exponent(rhs) {
+                    Some(exponent) => exponent,
+                    None => {
+                        return Err(ServerError::ArithmeticDomain(
+                            format!("exponent {rhs} is not an integer").into(),
+                        ))
+                    }
+                };
+                if lhs.is_zero() && exponent < 0 {
+                    return Err(ServerError::DivisionByZero);
+                }
+                match exponent.cmp(&0) {
+                    Ordering::Greater => lhs.powi_with_context(exponent, &ctx.to_context()),
+                    Ordering::Equal => BigDecimal::from(1),
+                    // divide by the power of absolute exponent to avoid padding zeros.
+                    Ordering::Less => {
+                        let power =
+                            lhs.powi_with_context(exponent.saturating_neg(), &ctx.to_context());
+                        ctx.divide(&BigDecimal::from(1), &power)?
+                    }
+                }
+            }
+            BinaryOps::Modulo => {
+                if rhs.is_zero() {
+                    return Err(ServerError::DivisionByZero);
+                }
+                if lhs.abs() < rhs.abs() {
+                    lhs.clone()
+                } else {
+                    check_scale_gap(self, lhs, rhs)?;
+                    lhs % rhs
+                }
+            }
+            BinaryOps::Min => lhs.min(rhs).clone(),
+            BinaryOps::Max if rhs > lhs => rhs.clone(),
+            BinaryOps::Max => lhs.clone(),
+        };
+
+        Ok(ctx.round(res))
+    }
+}
+
+impl UnaryOps {
+    /// compute `op value` rounded by `ctx`, return [`ServerError::ArithmeticDomain`] on square
+    /// root of negative number.
+    ///
+    /// [`ServerError::ArithmeticDomain`]: crate::error::ServerError::ArithmeticDomain
+    pub(crate) fn apply(
+        &self,
+        value: &BigDecimal,
+        ctx: &MathContext,
+    ) -> Result<BigDecimal, ServerError> {
+        let res = match self {
+            UnaryOps::Abs => value.abs(),
+            UnaryOps::Neg => -value,
+            UnaryOps::Sqrt => match value.sqrt_with_context(&ctx.to_context()) {
+                Some(root) => root,
+                None => {
+                    return Err(ServerError::ArithmeticDomain(
+                        format!("square root of negative number {value}").into(),
+                    ))
+                }
+            },
+            UnaryOps::Round => integral(value, ctx.rounding.into()),
+            UnaryOps::Floor => integral(value, bigdecimal::RoundingMode::Floor),
+            UnaryOps::Ceil => integral(value, bigdecimal::RoundingMode::Ceiling),
+            _ => return transcendental(self, value, ctx.bounded()),
+        };
+
+        Ok(ctx.round(res))
+    }
+}
+
+impl CompareOps {
+    /// compare `lhs` with `rhs` numerically, e.g. `1.0` equals `1.00`, return `-1`, `0` or `1` for
+    /// [`CompareOps::Compare`] and boolean for the others.
+    ///
+    /// [`CompareOps::Compare`]: crate::CompareOps::Compare
+    pub(crate) fn apply(&self, lhs: &BigDecimal, rhs: &BigDecimal) -> Value {
+        let ordering = lhs.cmp(rhs);
+        match self {
+            CompareOps::Compare => Value::from(ordering as i8),
+            CompareOps::Eq => Value::Bool(ordering == Ordering::Equal),
+            CompareOps::Lt => Value::Bool(ordering == Ordering::Less),
+            CompareOps::Gt => Value::Bool(ordering == Ordering::Greater),
+        }
+    }
+}
+
+impl PredicateOps {
+    /// test `value` by the predicate.
+    pub(crate) fn apply(&self, value: &BigDecimal) -> bool {
+        match self {
+            PredicateOps::IsZero => value.is_zero(),
+            PredicateOps::IsNegative => value.is_negative(),
+        }
+    }
+}
+
+impl AggregateOps {
+    /// compute the aggregate of `values` rounded by `ctx`, return
+    /// [`ServerError::ArithmeticDomain`] if `values` is empty except for `Sum`, `Product` and
+    /// `Count`.
+    ///
+    /// [`ServerError::ArithmeticDomain`]: crate::error::ServerError::ArithmeticDomain
+    pub(crate) fn apply(
+        &self,
+        values: &[BigDecimal],
+        ctx: &MathContext,
+    ) -> Result<BigDecimal, ServerError> {
+        let count = BigDecimal::from(values.len() as u64);
+        let add = |acc: BigDecimal, value: &BigDecimal| {
+            check_scale_gap(&BinaryOps::Add, &acc, value).map(|_| acc + value)
+        };
+        let sum = || values.iter().try_fold(BigDecimal::zero(), &add);
+        let res = match self {
+            AggregateOps::Sum => sum()?,
+            AggregateOps::Product => values
+                .iter()
+                .fold(BigDecimal::from(1), |acc, value| acc * value),
+            AggregateOps::Count => count,
+            _ if values.is_empty() => {
+                return Err(ServerError::ArithmeticDomain(
+                    format!("{self} of no key entries is undefined").into(),
+                ))
+            }
+            AggregateOps::Mean => ctx.divide(&sum()?, &count)?,
+            AggregateOps::Min => values.iter().min().cloned().unwrap_or_default(),
+            AggregateOps::Max => values.iter().max().cloned().unwrap_or_default(),
+            // (n * sum(x^2) - sum(x)^2) / n^2 keeps the numerator exact.
+            AggregateOps::Variance => {
+                let sum_of_squares = values
+                    .iter()
+                    .try_fold(BigDecimal::zero(), |acc, value| add(acc, &(value * value)))?;
+                let sum = sum()?;
+                let (lhs, rhs) = (&count * sum_of_squares, &sum * &sum);
+                check_scale_gap(&BinaryOps::Subtract, &lhs, &rhs)?;
+                ctx.divide(&(lhs - rhs), &(&count * &count))?
+            }
+        };
+
+        Ok(ctx.round(res))
+    }
+}
+
+/// the maximum difference of scales of operands aligned by `add`, `subtract` and `modulo`,
+/// beyond which the aligned operand is an integer of as many more digits, e.g. `1e999999999`.
+const MAX_SCALE_GAP: u64 = 100_000;
+
+/// return [`ServerError::ArithmeticDomain`] if the scales of `lhs` and `rhs` are too far apart
+/// to be aligned by `op`.
+///
+/// [`ServerError::ArithmeticDomain`]: crate::error::ServerError::ArithmeticDomain
+fn check_scale_gap(op: &BinaryOps, lhs: &BigDecimal, rhs: &BigDecimal) -> Result<(), ServerError> {
+    let gap = lhs
+        .fractional_digit_count()
+        .abs_diff(rhs.fractional_digit_count());
+    if gap > MAX_SCALE_GAP {
+        return Err(ServerError::ArithmeticDomain(
+            format!("{op} of {lhs} and {rhs} is out of range").into(),
+        ));
+    }
+    Ok(())
+}
+
+/// the integral exponent of `Pow` if `value` is an integer within `i64`.
+///
+/// NOTE: `value` is normalized instead of testing its fractional digits, which builds an
+/// integer of as many digits as its scale.
+fn integer_exponent(value: &BigDecimal) -> Option<i64> {
+    let normalized = value.normalized();
+    match normalized.fractional_digit_count() {
+        // `i64::MAX` is less than `10^19`.
+        -18..=0 => normalized.to_i64(),
+        _ => None,
+    }
+}
+
+/// round `value` to an integer by `mode`.
+///
+/// NOTE: rounding is done without building an integer of as many digits as the scale of
+/// `value`, a value with non-positive scale is an integer already, and a value less than `0.1`
+/// in absolute value rounds the same as `0.01` of its sign.
+fn integral(value: &BigDecimal, mode: bigdecimal::RoundingMode) -> BigDecimal {
+    if value.is_zero() {
+        return BigDecimal::zero();
+    }
+    if value.fractional_digit_count() <= 0 {
+        return value.clone();
+    }
+    if magnitude(value) >= -1 {
+        return value.with_scale_round(0, mode);
+    }
+    let sign = if value.is_negative() { -1 } else { 1 };
+    BigDecimal::new(BigInt::from(sign), 2).with_scale_round(0, mode)
+}
+
+/// the number of guard digits kept by intermediate results of transcendental functions.
+const GUARD_DIGITS: u64 = 10;
+
+/// the maximum order of magnitude of argument of `exp`, beyond which the exponent of result
+/// overflows.
+const MAX_EXP_MAGNITUDE: i64 = 15;
+
+/// the maximum order of magnitude of argument of `sin` and `cos`, beyond which reduction into
+/// `[-pi, pi]` needs as many digits of `pi` as the integral part of the argument.
+const MAX_TRIG_MAGNITUDE: i64 = 100;
+
+/// compute the transcendental function `op` of `value` rounded by `ctx`, which MUST have a
+/// bounded precision.
+fn transcendental(
+    op: &UnaryOps,
+    value: &BigDecimal,
+    ctx: MathContext,
+) -> Result<BigDecimal, ServerError> {
+    let digits = ctx.capped_precision().unwrap_or(0) + GUARD_DIGITS;
+    let negligible_to_one = !value.is_zero() && magnitude(value) < -(digits as i64);
+    if negligible_to_one && !matches!(op, UnaryOps::Ln | UnaryOps::Log) {
+        return Ok(ctx.round(first_order(op, value, digits)));
+    }
+    let res = match op {
+        UnaryOps::Exp => {
+            if value.is_zero() {
+                return Ok(BigDecimal::from(1));
+            }
+            if magnitude(value) > MAX_EXP_MAGNITUDE {
+                return Err(ServerError::ArithmeticDomain(
+                    format!("exponential of {value} is out of range").into(),
+                ));
+            }
+            exp(value, digits)?
+        }
+        UnaryOps::Ln | UnaryOps::Log => {
+            if !value.is_positive() {
+                return Err(ServerError::ArithmeticDomain(
+                    format!("logarithm of non-positive number {value}").into(),
+                ));
+            }
+            let normalized = value.normalized();
+            if normalized.as_bigint_and_exponent().0 == BigInt::from(1) {
+                // logarithm of exact power of 10.
+                let exponent = BigDecimal::from(magnitude(value));
+                return Ok(match op {
+                    UnaryOps::Log => exponent,
+                    _ if exponent.is_zero() => exponent,
+                    _ => ctx.round(exponent * ln10(digits)?),
+                });
+            }
+            let wctx = MathContext::working(digits);
+            match op {
+                UnaryOps::Log => wctx.divide(&ln(value, digits)?, &ln10(digits)?)?,
+                _ => ln(value, digits)?,
+            }
+        }
+        UnaryOps::Sin | UnaryOps::Cos => {
+            if value.is_zero() {
+                return Ok(match op {
+                    UnaryOps::Sin => BigDecimal::zero(),
+                    _ => BigDecimal::from(1),
+                });
+            }
+            if magnitude(value) > MAX_TRIG_MAGNITUDE {
+                return Err(ServerError::ArithmeticDomain(
+                    format!("{op} of {value} is out of range").into(),
+                ));
+            }
+            sin_cos(value, digits, matches!(op, UnaryOps::Cos))?
+        }
+        UnaryOps::Atan => {
+            if value.is_zero() {
+                return Ok(BigDecimal::zero());
+            }
+            atan(value, digits)?
+        }
+        _ => unreachable!("{op} is not a transcendental function"),
+    };
+
+    Ok(ctx.round(res))
+}
+
+/// approximate `exp`, `sin`, `cos` or `atan` of non-zero `x` negligible to 1 with `digits`
+/// significant digits to first order, e.g. `sin(x) = x`, whose powers would overflow the scale.
+///
+/// NOTE: `1 + x` is represented by `1 + 10^-(digits + 1)` signed as `x`, which rounds the same as
+/// `1 + x` with fewer than `digits` significant digits.
+fn first_order(op: &UnaryOps, x: &BigDecimal, digits: u64) -> BigDecimal {
+    let epsilon = BigDecimal::new(BigInt::from(1), digits as i64 + 1);
+    let one = BigDecimal::from(1);
+    match op {
+        UnaryOps::Exp if x.is_negative() => one - epsilon,
+        UnaryOps::Exp => one + epsilon,
+        UnaryOps::Cos => one - epsilon,
+        UnaryOps::Sin | UnaryOps::Atan => x.clone(),
+        _ => unreachable!("{op} is not approximated to first order"),
+    }
+}
+
+/// the order of magnitude of non-zero `x`, i.e. `floor(log10(|x|))`.
+fn magnitude(x: &BigDecimal) -> i64 {
+    (x.digits() as i64)
+        .saturating_sub(x.as_bigint_and_exponent().1)
+        .saturating_sub(1)
+}
+
+/// whether `term` is negligible to `sum` with `digits` significant digits.
+fn negligible(term: &BigDecimal, sum: &BigDecimal, digits: u64) -> bool {
+    term.is_zero() || (!sum.is_zero() && magnitude(term) < magnitude(sum) - digits as i64)
+}
+
+/// compute `e^x` by taylor series of `x / 2^n`, then square the sum `n` times.
+fn exp(x: &BigDecimal, digits: u64) -> Result<BigDecimal, ServerError> {
+    let half = BigDecimal::new(BigInt::from(5), 1);
+    let mut reduced = x.clone();
+    let mut halvings = 0;
+    while reduced.abs() >= half {
+        reduced = reduced.half();
+        halvings += 1;
+    }
+    // squaring doubles the relative error, which costs a digit per 3 squarings.
+    let digits = digits + halvings / 3 + 1;
+    let wctx = MathContext::working(digits);
+
+    let mut sum = BigDecimal::from(1);
+    let mut term = BigDecimal::from(1);
+    let mut k = 1;
+    loop {
+        term = wctx.divide(&wctx.round(term * &reduced), &BigDecimal::from(k))?;
+        if negligible(&term, &sum, digits) {
+            break;
+        }
+        sum = wctx.round(sum + &term);
+        k += 1;
+    }
+    for _ in 0..halvings {
+        sum = wctx.round(&sum * &sum);
+    }
+
+    Ok(sum)
+}
+
+/// compute the natural logarithm of positive `x`.
+///
+/// NOTE: `x` is reduced to `m * 2^j * 10^e` where `m` is close to 1, then
+/// `ln(x) = ln(m) + j * ln(2) + e * ln(10)`.
+fn ln(x: &BigDecimal, digits: u64) -> Result<BigDecimal, ServerError> {
+    let one = BigDecimal::from(1);
+    if (x - &one).abs() < BigDecimal::new(BigInt::from(5), 1) {
+        return ln_near_one(x, digits);
+    }
+
+    let exponent = magnitude(x);
+    // keep extra digits for the integral part of `e * ln(10)`.
+    let digits = digits + BigDecimal::from(exponent).digits() + 2;
+    let wctx = MathContext::working(digits);
+    let (int_val, _) = x.as_bigint_and_exponent();
+    let mantissa = BigDecimal::new(int_val, x.digits() as i64 - 1);
+    let j = mantissa.to_f64().map_or(0, |m| m.log2().round() as u32);
+    // divide by `2^j` exactly by multiplying `5^j / 10^j`.
+    let reduced = &mantissa * BigDecimal::new(BigInt::from(5).pow(j), j as i64);
+
+    let mut res = ln_near_one(&reduced, digits)?;
+    if j != 0 {
+        res = wctx.round(res + ln2(digits)? * BigDecimal::from(j));
+    }
+    if exponent != 0 {
+        res = wctx.round(res + ln10(digits)? * BigDecimal::from(exponent));
+    }
+
+    Ok(res)
+}
+
+/// compute `ln(x) = 2 * atanh((x - 1) / (x + 1))` for `x` close to 1.
+fn ln_near_one(x: &BigDecimal, digits: u64) -> Result<BigDecimal, ServerError> {
+    let one = BigDecimal::from(1);
+    let wctx = MathContext::working(digits);
+    let z = wctx.divide(&(x - &one), &(x + &one))?;
+    Ok(arctan_series(&z, digits, false)? * BigDecimal::from(2))
+}
+
+/// compute `ln(2) = 2 * atanh(1/3)`.
+fn ln2(digits: u64) -> Result<BigDecimal, ServerError> {
+    let wctx = MathContext::working(digits);
+    let z = wctx.divide(&BigDecimal::from(1), &BigDecimal::from(3))?;
+    Ok(arctan_series(&z, digits, false)? * BigDecimal::from(2))
+}
+
+/// compute `ln(10) = 3 * ln(2) + 2 * atanh(1/9)`.
+fn ln10(digits: u64) -> Result<BigDecimal, ServerError> {
+    let wctx = MathContext::working(digits);
+    let z = wctx.divide(&BigDecimal::from(1), &BigDecimal::from(9))?;
+    let ln_five_quarters = arctan_series(&z, digits, false)? * BigDecimal::from(2);
+    Ok(wctx.round(ln2(digits)? * BigDecimal::from(3) + ln_five_quarters))
+}
+
+/// compute `pi = 16 * atan(1/5) - 4 * atan(1/239)` by Machin's formula.
+fn pi(digits: u64) -> Result<BigDecimal, ServerError> {
+    let wctx = MathContext::working(digits);
+    let one = BigDecimal::from(1);
+    let fifth = wctx.divide(&one, &BigDecimal::from(5))?;
+    let inverse_239 = wctx.divide(&one, &BigDecimal::from(239))?;
+    Ok(wctx.round(
+        arctan_series(&fifth, digits, true)? * BigDecimal::from(16)
+            - arctan_series(&inverse_239, digits, true)? * BigDecimal::from(4),
+    ))
+}
+
+/// compute the series `z + s*z^3/3 + z^5/5 + s*z^7/7 + ...` for `|z| < 1`, which is `atan(z)`
+/// if `alternating` where `s = -1`, or `atanh(z)` otherwise.
+fn arctan_series(
+    z: &BigDecimal,
+    digits: u64,
+    alternating: bool,
+) -> Result<BigDecimal, ServerError> {
+    let wctx = MathContext::working(digits);
+    let z_squared = wctx.round(z * z);
+    let mut power = z.clone();
+    let mut sum = z.clone();
+    let mut k = 1;
+    loop {
+        power = wctx.round(power * &z_squared);
+        if alternating {
+            power = -power;
+        }
+        let term = wctx.divide(&power, &BigDecimal::from(2 * k + 1))?;
+        if negligible(&term, &sum, digits) {
+            break;
+        }
+        sum = wctx.round(sum + term);
+        k += 1;
+    }
+
+    Ok(sum)
+}
+
+/// compute `sin(x)`, or `cos(x)` if `cosine`, by taylor series of `x` reduced into `[-pi, pi]`.
+fn sin_cos(x: &BigDecimal, digits: u64, cosine: bool) -> Result<BigDecimal, ServerError> {
+    // keep extra digits for the integral part of `x` cancelled by reduction.
+    let digits = digits + magnitude(x).max(0) as u64 + 2;
+    let wctx = MathContext::working(digits);
+    let two_pi = pi(digits)? * BigDecimal::from(2);
+    let turns = wctx
+        .divide(x, &two_pi)?
+        .with_scale_round(0, bigdecimal::RoundingMode::HalfEven);
+    let reduced = wctx.round(x - &two_pi * turns);
+
+    let r_squared = wctx.round(&reduced * &reduced);
+    let (mut term, mut k) = if cosine {
+        (BigDecimal::from(1), 0)
+    } else {
+        (reduced, 1)
+    };
+    let mut sum = term.clone();
+    loop {
+        let divisor = BigDecimal::from((k + 1) * (k + 2));
+        term = -wctx.divide(&wctx.round(term * &r_squared), &divisor)?;
+        if negligible(&term, &sum, digits) {
+            break;
+        }
+        sum = wctx.round(sum + &term);
+        k += 2;
+    }
+
+    Ok(sum)
+}
+
+/// compute `atan(x)`, the argument is reduced by `atan(x) = pi/2 - atan(1/x)` for `x > 1` and
+/// halved by `atan(x) = 2 * atan(x / (1 + sqrt(1 + x^2)))` until `|x| <= 0.1`.
+fn atan(x: &BigDecimal, digits: u64) -> Result<BigDecimal, ServerError> {
+    let wctx = MathContext::working(digits);
+    let one = BigDecimal::from(1);
+    if x.abs() > one {
+        let half_pi = pi(digits)?.half();
+        // `atan(1/x)` is negligible to `pi/2`.
+        let res = if magnitude(x) > digits as i64 {
+            half_pi
+        } else {
+            half_pi - atan(&wctx.divide(&one, &x.abs())?, digits)?
+        };
+        return Ok(if x.is_negative() { -res } else { res });
+    }
+
+    let tenth = BigDecimal::new(BigInt::from(1), 1);
+    let mut reduced = x.clone();
+    let mut doublings = 0;
+    while reduced.abs() > tenth {
+        let root = (&one + wctx.round(&reduced * &reduced))
+            .sqrt_with_context(&wctx.to_context())
+            .unwrap_or_else(|| one.clone());
+        reduced = wctx.divide(&reduced, &(&one + root))?;
+        doublings += 1;
+    }
+
+    Ok(arctan_series(&reduced, digits, true)? * BigDecimal::from(2_u32.pow(doublings)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decimal(s: &str) -> BigDecimal {
+        BigDecimal::from_str(s).unwrap()
+    }
+
+    fn binary(op: BinaryOps, lhs: &str, rhs: &str) -> Result<BigDecimal, ServerError> {
+        op.apply(&decimal(lhs), &decimal(rhs), &MathContext::default())
+    }
+
+    fn unary(op: UnaryOps, value: &str, precision: u64) -> Result<BigDecimal, ServerError> {
+        let ctx = MathContext::new(NonZeroU64::new(precision).unwrap(), RoundingMode::HalfEven);
+        op.apply(&decimal(value), &ctx)
+    }
+
+    #[test]
+    fn transcendental_digits() {
+        let cases = [
+            (UnaryOps::Exp, "1", "2.71828182845904523536028747135"),
+            (UnaryOps::Exp, "-10", "0.0000453999297624848515355915155606"),
+            (UnaryOps::Ln, "2", "0.693147180559945309417232121458"),
+            (UnaryOps::Ln, "0.001", "-6.90775527898213705205397436405"),
+            (UnaryOps::Log, "2", "0.301029995663981195213738894724"),
+            (UnaryOps::Log, "1000", "3"),
+            (UnaryOps::Sin, "1", "0.841470984807896506652502321630"),
+            (UnaryOps::Cos, "1", "0.540302305868139717400936607443"),
+            (UnaryOps::Atan, "1", "0.785398163397448309615660845820"),
+            (UnaryOps::Atan, "-3", "-1.24904577239825442582991707728"),
+        ];
+        for (op, value, expect) in cases {
+            let res = unary(op.clone(), value, 30).unwrap();
+            assert_eq!(res, decimal(expect), "{op} of {value}");
+        }
+    }
+
+    #[test]
+    fn transcendental_out_of_domain() {
+        for (op, value) in [
+            (UnaryOps::Ln, "0"),
+            (UnaryOps::Log, "-1"),
+            (UnaryOps::Exp, "1e16"),
+            (UnaryOps::Sin, "1e101"),
+            (UnaryOps::Cos, "-1e101"),
+        ] {
+            assert!(
+                matches!(
+                    unary(op.clone(), value, 30),
+                    Err(ServerError::ArithmeticDomain(_))
+                ),
+                "{op} of {value}"
+            );
+        }
+    }
+
+    #[test]
+    fn division_by_zero() {
+        for zero in ["0", "0.00", "-0"] {
+            assert!(matches!(
+                binary(BinaryOps::Divide, "1", zero),
+                Err(ServerError::DivisionByZero)
+            ));
+            assert!(matches!(
+                binary(BinaryOps::Modulo, "1", zero),
+                Err(ServerError::DivisionByZero)
+            ));
+        }
+        assert!(matches!(
+            binary(BinaryOps::Divide, "0", "0"),
+            Err(ServerError::DivisionByZero)
+        ));
+    }
+
+    #[test]
+    fn negative_power_of_zero() {
+        assert!(matches!(
+            binary(BinaryOps::Pow, "0", "-1"),
+            Err(ServerError::DivisionByZero)
+        ));
+        assert!(matches!(
+            binary(BinaryOps::Pow, "0.0", "-2"),
+            Err(ServerError::DivisionByZero)
+        ));
+        assert_eq!(binary(BinaryOps::Pow, "0", "0").unwrap(), decimal("1"));
+        assert_eq!(binary(BinaryOps::Pow, "2", "-2").unwrap(), decimal("0.25"));
+    }
+
+    #[test]
+    fn round_carry() {
+        let ctx = MathContext::new(NonZeroU64::new(3).unwrap(), RoundingMode::Up);
+        assert_eq!(ctx.round(decimal("0.9995")).to_string(), "1.00");
+        assert_eq!(ctx.round(decimal("99.91")).to_string(), "100");
+    }
+
+    fn binary_with(
+        op: BinaryOps,
+        lhs: &str,
+        rhs: &str,
+        precision: u64,
+        rounding: RoundingMode,
+    ) -> String {
+        let ctx = MathContext::new(NonZeroU64::new(precision).unwrap(), rounding);
+        op.apply(&decimal(lhs), &decimal(rhs), &ctx)
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn invalid_exponent() {
+        for exponent in ["0.5", "-1.25", "1e19", "-9223372036854775809"] {
+            assert!(
+                matches!(
+                    binary(BinaryOps::Pow, "2", exponent),
+                    Err(ServerError::ArithmeticDomain(_))
+                ),
+                "{exponent}"
+            );
+        }
+        assert_eq!(binary(BinaryOps::Pow, "2", "3.000").unwrap(), decimal("8"));
+    }
+
+    #[test]
+    fn modulo_sign_follows_dividend() {
+        assert_eq!(binary(BinaryOps::Modulo, "7", "3").unwrap(), decimal("1"));
+        assert_eq!(binary(BinaryOps::Modulo, "-7", "3").unwrap(), decimal("-1"));
+        assert_eq!(binary(BinaryOps::Modulo, "7", "-3").unwrap(), decimal("1"));
+        assert_eq!(
+            binary(BinaryOps::Modulo, "-7.5", "2").unwrap(),
+            decimal("-1.5")
+        );
+    }
+
+    #[test]
+    fn min_max_of_equal_values() {
+        for op in [BinaryOps::Min, BinaryOps::Max] {
+            let res = binary(op.clone(), "1.0", "1.00").unwrap();
+            assert_eq!(res.to_string(), "1.0", "{op}");
+            let res = binary(op.clone(), "1.00", "1.0").unwrap();
+            assert_eq!(res.to_string(), "1.00", "{op}");
+        }
+        assert_eq!(binary(BinaryOps::Min, "-1", "0.5").unwrap(), decimal("-1"));
+        assert_eq!(binary(BinaryOps::Max, "-1", "0.5").unwrap(), decimal("0.5"));
+    }
+
+    #[test]
+    fn rounding_of_exact_operations() {
+        use RoundingMode::*;
+
+        assert_eq!(
+            binary_with(BinaryOps::Add, "1.234", "1", 3, HalfEven),
+            "2.23"
+        );
+        assert_eq!(
+            binary_with(BinaryOps::Add, "1.235", "1", 3, HalfEven),
+            "2.24"
+        );
+        assert_eq!(
+            binary_with(BinaryOps::Add, "1.225", "1", 3, HalfEven),
+            "2.22"
+        );
+        assert_eq!(binary_with(BinaryOps::Add, "1.225", "1", 3, HalfUp), "2.23");
+        assert_eq!(
+            binary_with(BinaryOps::Subtract, "1", "0.0005", 3, Down),
+            "0.999"
+        );
+        assert_eq!(
+            binary_with(BinaryOps::Subtract, "1", "0.0005", 3, Up),
+            "1.00"
+        );
+        assert_eq!(
+            binary_with(BinaryOps::Subtract, "0", "1.001", 2, Floor),
+            "-1.1"
+        );
+        assert_eq!(
+            binary_with(BinaryOps::Subtract, "0", "1.001", 2, Ceiling),
+            "-1.0"
+        );
+        assert_eq!(
+            binary_with(BinaryOps::Multiply, "1.25", "1.01", 3, HalfEven),
+            "1.26"
+        );
+        assert_eq!(
+            binary_with(BinaryOps::Multiply, "1.25", "1.01", 3, Up),
+            "1.27"
+        );
+        assert_eq!(
+            binary_with(BinaryOps::Multiply, "123", "456", 2, HalfEven),
+            "56000"
+        );
+        // results fitting in precision are not padded.
+        assert_eq!(binary_with(BinaryOps::Add, "1", "1", 5, HalfEven), "2");
+    }
+
+    #[test]
+    fn integral_of_extreme_scales() {
+        let round = |value: &str, rounding: RoundingMode| {
+            let ctx = MathContext {
+                precision: None,
+                rounding,
+            };
+            UnaryOps::Round.apply(&decimal(value), &ctx).unwrap()
+        };
+        for op in [UnaryOps::Round, UnaryOps::Floor, UnaryOps::Ceil] {
+            let res = op.apply(&decimal("1e999999999"), &MathContext::default());
+            assert_eq!(res.unwrap(), decimal("1e999999999"), "{op}");
+        }
+        let tiny = "1e-999999999";
+        assert_eq!(round(tiny, RoundingMode::HalfEven), decimal("0"));
+        assert_eq!(round(tiny, RoundingMode::Up), decimal("1"));
+        assert_eq!(
+            round(&format!("-{tiny}"), RoundingMode::Floor),
+            decimal("-1")
+        );
+        assert_eq!(
+            round(&format!("-{tiny}"), RoundingMode::Ceiling),
+            decimal("0")
+        );
+        assert_eq!(round("0.09", RoundingMode::Down), decimal("0"));
+        assert_eq!(round("0.5", RoundingMode::HalfUp), decimal("1"));
+        assert_eq!(round("2.5", RoundingMode::HalfEven), decimal("2"));
+        assert_eq!(round("0E-999999999", RoundingMode::Up), decimal("0"));
+    }
+
+    #[test]
+    fn alignment_of_extreme_scales() {
+        assert_eq!(
+            binary(BinaryOps::Modulo, "1e-999999999", "7").unwrap(),
+            decimal("1e-999999999")
+        );
+        for (op, lhs, rhs) in [
+            (BinaryOps::Modulo, "1e999999999", "7"),
+            (BinaryOps::Modulo, "7", "1e-999999999"),
+            (BinaryOps::Add, "1e999999999", "1"),
+            (BinaryOps::Subtract, "1", "1e-999999999"),
+        ] {
+            assert!(
+                matches!(
+                    binary(op.clone(), lhs, rhs),
+                    Err(ServerError::ArithmeticDomain(_))
+                ),
+                "{op} of {lhs} and {rhs}"
+            );
+        }
+        assert_eq!(
+            binary(BinaryOps::Add, "1e1000", "1").unwrap().digits(),
+            1001
+        );
+        assert!(matches!(
+            binary(BinaryOps::Pow, "2", "1e-999999999"),
+            Err(ServerError::ArithmeticDomain(_))
+        ));
+        assert!(matches!(
+            AggregateOps::Sum.apply(
+                &[decimal("1e999999999"), decimal("1")],
+                &MathContext::default()
+            ),
+            Err(ServerError::ArithmeticDomain(_))
+        ));
+    }
+}
@@ -0,0 +1,697 @@
+use crate::database::Page;
+use crate::error::{ClientError, ErrorCode, ErrorObject};
+use crate::frame;
+use crate::prelude::v1::{RequestBuilder, RespBody};
+use crate::{AggregateOps, BinaryOps, CompareOps, Method, PredicateOps, UnaryOps};
+
+use std::cmp::Ordering as CmpOrdering;
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bigdecimal::BigDecimal;
+use log::{error, warn};
+use serde_json::Value;
+use tokio::{net::UdpSocket, sync::oneshot, task::JoinHandle, time::timeout};
+
+const DEFAULT_SERVER_PORT: u16 = 9999;
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+const UDP_DATAGRAM_MAX_SIZE: usize = 65536;
+
+/// requests waiting for JSON response, identified by JSON request `id`.
+type Pending = Arc<Mutex<HashMap<usize, oneshot::Sender<RespBody>>>>;
+
+/// The policy of resending JSON request which is not responded in time, UDP datagrams are lost
+/// silently.
+///
+/// NOTE:
+///     - the request is resent with the same `id`, so the server replays the response of the
+///     original request instead of applying it twice.
+///     - the backoff is waited after each attempt timed out, and it is multiplied by
+///     `multiplier` for the next attempt up to `max_backoff`.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// the number of resending attempts after the first one, `0` disables retry.
+    pub max_retries: usize,
+    /// the duration waited before the first resending attempt.
+    pub initial_backoff: Duration,
+    /// the factor applied to backoff after each resending attempt.
+    pub multiplier: u32,
+    /// the upper bound of backoff.
+    pub max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// never resend JSON request.
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_retries: 0,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(100),
+            multiplier: 2,
+            max_backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+/// The right-hand side operand of arithmetic methods, which is resolved by the server either
+/// from a stored key entry or as a literal number.
+#[derive(Debug, Clone)]
+pub enum Operand<'a> {
+    /// name of key entry in user database.
+    Key(&'a str),
+    /// big decimal number used as is.
+    Value(BigDecimal),
+}
+
+impl From<Operand<'_>> for String {
+    fn from(value: Operand<'_>) -> Self {
+        match value {
+            Operand::Key(key) => key.to_string(),
+            Operand::Value(number) => number.to_string(),
+        }
+    }
+}
+
+/// Used to configure and connect [`Client`].
+///
+/// [`Client`]: crate::client::Client
+pub struct ClientBuilder {
+    server_addr: SocketAddr,
+    request_timeout: Duration,
+    retry_policy: RetryPolicy,
+    max_datagram_size: usize,
+}
+
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        ClientBuilder {
+            server_addr: SocketAddr::from((Ipv4Addr::LOCALHOST, DEFAULT_SERVER_PORT)),
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            retry_policy: RetryPolicy::default(),
+            max_datagram_size: UDP_DATAGRAM_MAX_SIZE,
+        }
+    }
+}
+
+impl ClientBuilder {
+    /// set the socket address of `acrudjson` server, default to `127.0.0.1:9999`.
+    pub fn server_addr(mut self, addr: SocketAddr) -> Self {
+        self.server_addr = addr;
+        self
+    }
+
+    /// set the duration to wait for JSON response of each attempt, [`ClientError::Timeout`] is
+    /// returned when every attempt timed out, default to 5 seconds.
+    ///
+    /// [`ClientError::Timeout`]: crate::error::ClientError::Timeout
+    pub fn request_timeout(mut self, duration: Duration) -> Self {
+        self.request_timeout = duration;
+        self
+    }
+
+    /// set the policy of resending JSON request on timeout, default to
+    /// [`RetryPolicy::default`].
+    ///
+    /// [`RetryPolicy::default`]: crate::client::RetryPolicy
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// set the size of receiving buffer, datagrams larger than it are truncated, default to
+    /// 65536 bytes.
+    pub fn max_datagram_size(mut self, size: usize) -> Self {
+        self.max_datagram_size = size;
+        self
+    }
+
+    /// bind a UDP socket on an ephemeral port connected to the server, then start receiving
+    /// JSON responses in background.
+    pub async fn connect(self) -> Result<Client, ClientError> {
+        let bind_addr = if self.server_addr.is_ipv4() {
+            SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))
+        } else {
+            SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0))
+        };
+        let socket = Arc::new(UdpSocket::bind(bind_addr).await?);
+        socket.connect(self.server_addr).await?;
+        let pending: Pending = Arc::new(Mutex::new(HashMap::new()));
+        let recv_task = tokio::spawn(receive(
+            socket.clone(),
+            pending.clone(),
+            self.max_datagram_size,
+        ));
+
+        Ok(Client {
+            socket,
+            next_id: AtomicUsize::new(1),
+            pending,
+            request_timeout: self.request_timeout,
+            retry_policy: self.retry_policy,
+            recv_task,
+        })
+    }
+}
+
+/// An asynchronous UDP client of `acrudjson` server based on JSON-RPC 1.0 specification.
+///
+/// NOTE:
+///     - the `id` of each JSON request is assigned by the client in ascending order, and the
+///     JSON response is resolved to the awaiting call by the same `id`.
+///     - responses arriving after the call timed out are discarded.
+pub struct Client {
+    socket: Arc<UdpSocket>,
+    next_id: AtomicUsize,
+    pending: Pending,
+    request_timeout: Duration,
+    retry_policy: RetryPolicy,
+    recv_task: JoinHandle<()>,
+}
+
+impl Client {
+    /// creates [`ClientBuilder`] with default configuration.
+    ///
+    /// [`ClientBuilder`]: crate::client::ClientBuilder
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::default()
+    }
+
+    /// invoke `method` with `params` on the server, and return the "result" member of JSON
+    /// response.
+    ///
+    /// NOTE: the "error" member of JSON response is returned as [`ClientError::Rpc`].
+    ///
+    /// [`ClientError::Rpc`]: crate::error::ClientError::Rpc
+    pub async fn call(&self, method: Method, params: Vec<String>) -> Result<Value, ClientError> {
+        self.call_with_timeout(method, params, self.request_timeout)
+            .await
+    }
+
+    /// same as [`Client::call`] but wait for JSON response of each attempt in `duration` instead
+    /// of the configured request timeout.
+    ///
+    /// [`Client::call`]: crate::client::Client::call
+    pub async fn call_with_timeout(
+        &self,
+        method: Method,
+        params: Vec<String>,
+        duration: Duration,
+    ) -> Result<Value, ClientError> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let payload = RequestBuilder::new(method, params, id).build()?;
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, tx);
+
+        let resp_body = match self.send_with_retry(&payload, rx, duration).await {
+            Ok(resp_body) => resp_body,
+            Err(e) => {
+                self.pending.lock().unwrap().remove(&id);
+                return Err(e);
+            }
+        };
+
+        match resp_body {
+            RespBody {
+                error: Some(error), ..
+            } => Err(ClientError::Rpc(error)),
+            RespBody {
+                result: Some(result),
+                ..
+            } => Ok(result),
+            _ => Err(ClientError::Rpc(ErrorObject::new(
+                ErrorCode::InternalError,
+                "JSON response contains neither result nor error.".to_string(),
+            ))),
+        }
+    }
+}
+
+/// Typed invocations of [`Method`]s.
+///
+/// NOTE: names of key entries must not be parsable as decimal numbers, otherwise they are
+/// resolved as literal numbers by the server.
+///
+/// [`Method`]: crate::Method
+impl Client {
+    /// create new key entry with `value`, fails if the key already exists.
+    pub async fn create(&self, key: &str, value: BigDecimal) -> Result<(), ClientError> {
+        self.call(Method::Create, vec![key.to_string(), value.to_string()])
+            .await?;
+        Ok(())
+    }
+
+    /// read the value of key entry.
+    pub async fn read(&self, key: &str) -> Result<BigDecimal, ClientError> {
+        let result = self.call(Method::Read, vec![key.to_string()]).await?;
+        decimal(result)
+    }
+
+    /// overwrite the value of existing key entry with `value`.
+    pub async fn update(&self, key: &str, value: BigDecimal) -> Result<(), ClientError> {
+        self.call(Method::Update, vec![key.to_string(), value.to_string()])
+            .await?;
+        Ok(())
+    }
+
+    /// overwrite the value of existing key entry with `value`, and return the previous value.
+    pub async fn update_return_old(
+        &self,
+        key: &str,
+        value: BigDecimal,
+    ) -> Result<BigDecimal, ClientError> {
+        let params = vec![
+            key.to_string(),
+            value.to_string(),
+            "return_old=true".to_string(),
+        ];
+        let result = self.call(Method::Update, params).await?;
+        decimal(result)
+    }
+
+    /// write `value` into key entry regardless of its existence, and return the previous value
+    /// if it's replaced, `None` if it's inserted.
+    pub async fn set(
+        &self,
+        key: &str,
+        value: BigDecimal,
+    ) -> Result<Option<BigDecimal>, ClientError> {
+        let result = self
+            .call(Method::Set, vec![key.to_string(), value.to_string()])
+            .await?;
+        match result.get("previous") {
+            Some(Value::String(literal)) => Ok(Some(BigDecimal::from_str(literal)?)),
+            _ => Ok(None),
+        }
+    }
+
+    /// update the value of key entry to `new_value` only if it's numerically equal to
+    /// `expected`, the current value is carried by "data" member of [`ClientError::Rpc`]
+    /// otherwise.
+    ///
+    /// [`ClientError::Rpc`]: crate::error::ClientError::Rpc
+    pub async fn cas(
+        &self,
+        key: &str,
+        expected: BigDecimal,
+        new_value: BigDecimal,
+    ) -> Result<(), ClientError> {
+        let params = vec![key.to_string(), expected.to_string(), new_value.to_string()];
+        self.call(Method::Cas, params).await?;
+        Ok(())
+    }
+
+    /// delete the key entry.
+    pub async fn delete(&self, key: &str) -> Result<(), ClientError> {
+        self.call(Method::Delete, vec![key.to_string()]).await?;
+        Ok(())
+    }
+
+    /// read many key entries in a consistent snapshot, the value of missing key entry is
+    /// `None`.
+    pub async fn mread(
+        &self,
+        keys: &[&str],
+    ) -> Result<HashMap<String, Option<BigDecimal>>, ClientError> {
+        let params = keys.iter().map(|key| key.to_string()).collect();
+        let result = self.call(Method::MultiRead, params).await?;
+        let values: HashMap<String, Option<String>> = serde_json::from_value(result)?;
+        let mut decimals = HashMap::with_capacity(values.len());
+        for (key, value) in values {
+            let value = match value {
+                Some(literal) => Some(BigDecimal::from_str(&literal)?),
+                None => None,
+            };
+            decimals.insert(key, value);
+        }
+        Ok(decimals)
+    }
+
+    /// create many key entries from `entries` atomically, fails if any of them already exists.
+    pub async fn mcreate(&self, entries: &[(&str, BigDecimal)]) -> Result<(), ClientError> {
+        self.call(Method::MultiCreate, pairs(entries)).await?;
+        Ok(())
+    }
+
+    /// overwrite many existing key entries from `entries` atomically, fails if any of them does
+    /// not exist.
+    pub async fn mupdate(&self, entries: &[(&str, BigDecimal)]) -> Result<(), ClientError> {
+        self.call(Method::MultiUpdate, pairs(entries)).await?;
+        Ok(())
+    }
+
+    /// delete the key entry, and return the deleted value.
+    pub async fn delete_return_old(&self, key: &str) -> Result<BigDecimal, ClientError> {
+        let params = vec![key.to_string(), "return_old=true".to_string()];
+        let result = self.call(Method::Delete, params).await?;
+        decimal(result)
+    }
+
+    /// add `amount` to the value of key entry atomically, and return the new value.
+    pub async fn increment(
+        &self,
+        key: &str,
+        amount: BigDecimal,
+    ) -> Result<BigDecimal, ClientError> {
+        let result = self
+            .call(Method::Increment, vec![key.to_string(), amount.to_string()])
+            .await?;
+        decimal(result)
+    }
+
+    /// subtract `amount` from the value of key entry atomically, and return the new value.
+    pub async fn decrement(
+        &self,
+        key: &str,
+        amount: BigDecimal,
+    ) -> Result<BigDecimal, ClientError> {
+        let result = self
+            .call(Method::Decrement, vec![key.to_string(), amount.to_string()])
+            .await?;
+        decimal(result)
+    }
+
+    /// compute `key + rhs`.
+    pub async fn add(&self, key: &str, rhs: Operand<'_>) -> Result<BigDecimal, ClientError> {
+        self.binary(BinaryOps::Add, key, rhs).await
+    }
+
+    /// compute `key - rhs`.
+    pub async fn subtract(&self, key: &str, rhs: Operand<'_>) -> Result<BigDecimal, ClientError> {
+        self.binary(BinaryOps::Subtract, key, rhs).await
+    }
+
+    /// compute `key * rhs`.
+    pub async fn multiply(&self, key: &str, rhs: Operand<'_>) -> Result<BigDecimal, ClientError> {
+        self.binary(BinaryOps::Multiply, key, rhs).await
+    }
+
+    /// compute `key / rhs`.
+    pub async fn divide(&self, key: &str, rhs: Operand<'_>) -> Result<BigDecimal, ClientError> {
+        self.binary(BinaryOps::Divide, key, rhs).await
+    }
+
+    /// compute `key ^ rhs`, the exponent `rhs` must be an integer.
+    pub async fn pow(&self, key: &str, rhs: Operand<'_>) -> Result<BigDecimal, ClientError> {
+        self.binary(BinaryOps::Pow, key, rhs).await
+    }
+
+    /// compute `key % rhs`.
+    pub async fn modulo(&self, key: &str, rhs: Operand<'_>) -> Result<BigDecimal, ClientError> {
+        self.binary(BinaryOps::Modulo, key, rhs).await
+    }
+
+    /// compute the minimum of `key` and `rhs`.
+    pub async fn min(&self, key: &str, rhs: Operand<'_>) -> Result<BigDecimal, ClientError> {
+        self.binary(BinaryOps::Min, key, rhs).await
+    }
+
+    /// compute the maximum of `key` and `rhs`.
+    pub async fn max(&self, key: &str, rhs: Operand<'_>) -> Result<BigDecimal, ClientError> {
+        self.binary(BinaryOps::Max, key, rhs).await
+    }
+
+    /// compute `op key`, e.g. the square root of `key` by [`UnaryOps::Sqrt`].
+    ///
+    /// [`UnaryOps::Sqrt`]: crate::UnaryOps::Sqrt
+    pub async fn unary(&self, op: UnaryOps, key: &str) -> Result<BigDecimal, ClientError> {
+        let result = self.call(Method::Unary(op), vec![key.to_string()]).await?;
+        decimal(result)
+    }
+
+    /// compare `key` with `rhs` numerically.
+    pub async fn compare(&self, key: &str, rhs: Operand<'_>) -> Result<CmpOrdering, ClientError> {
+        let result = self
+            .call(
+                Method::Compare(CompareOps::Compare),
+                vec![key.to_string(), rhs.into()],
+            )
+            .await?;
+        Ok(serde_json::from_value::<i8>(result)?.cmp(&0))
+    }
+
+    /// test whether `key` is numerically equal to `rhs`.
+    pub async fn eq(&self, key: &str, rhs: Operand<'_>) -> Result<bool, ClientError> {
+        self.test(Method::Compare(CompareOps::Eq), key, Some(rhs))
+            .await
+    }
+
+    /// test whether `key` is less than `rhs`.
+    pub async fn lt(&self, key: &str, rhs: Operand<'_>) -> Result<bool, ClientError> {
+        self.test(Method::Compare(CompareOps::Lt), key, Some(rhs))
+            .await
+    }
+
+    /// test whether `key` is greater than `rhs`.
+    pub async fn gt(&self, key: &str, rhs: Operand<'_>) -> Result<bool, ClientError> {
+        self.test(Method::Compare(CompareOps::Gt), key, Some(rhs))
+            .await
+    }
+
+    /// test whether the value of key entry is zero.
+    pub async fn is_zero(&self, key: &str) -> Result<bool, ClientError> {
+        self.test(Method::Predicate(PredicateOps::IsZero), key, None)
+            .await
+    }
+
+    /// test whether the value of key entry is negative.
+    pub async fn is_negative(&self, key: &str) -> Result<bool, ClientError> {
+        self.test(Method::Predicate(PredicateOps::IsNegative), key, None)
+            .await
+    }
+
+    /// evaluate infix `expression` over key entries, e.g. `"grav_const * mass / (r * r)"`, then
+    /// store the result into `target` key entry if provided.
+    pub async fn eval(
+        &self,
+        expression: &str,
+        target: Option<&str>,
+    ) -> Result<BigDecimal, ClientError> {
+        let mut params = vec![expression.to_string()];
+        params.extend(target.map(str::to_string));
+        let result = self.call(Method::Eval, params).await?;
+        decimal(result)
+    }
+
+    /// define `key` as derived key entry of infix `expression`, which is recomputed whenever
+    /// its dependencies are written, and return its current value.
+    pub async fn define(&self, key: &str, expression: &str) -> Result<BigDecimal, ClientError> {
+        let result = self
+            .call(
+                Method::Define,
+                vec![key.to_string(), expression.to_string()],
+            )
+            .await?;
+        decimal(result)
+    }
+
+    /// list key entries after `cursor` in ascending order of keys, at most `limit` entries per
+    /// page, the server default applies if `limit` is not provided.
+    pub async fn list(
+        &self,
+        cursor: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page, ClientError> {
+        let result = self.call(Method::List, page_options(cursor, limit)).await?;
+        page(result)
+    }
+
+    /// list key entries beginning with `prefix` after `cursor` in ascending order of keys.
+    pub async fn scan_prefix(
+        &self,
+        prefix: &str,
+        cursor: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page, ClientError> {
+        let mut params = vec![prefix.to_string()];
+        params.extend(page_options(cursor, limit));
+        let result = self.call(Method::ScanPrefix, params).await?;
+        page(result)
+    }
+
+    /// list key entries from `start` inclusive to `end` exclusive after `cursor` in ascending
+    /// order of keys.
+    pub async fn range(
+        &self,
+        start: &str,
+        end: &str,
+        cursor: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page, ClientError> {
+        let mut params = vec![start.to_string(), end.to_string()];
+        params.extend(page_options(cursor, limit));
+        let result = self.call(Method::Range, params).await?;
+        page(result)
+    }
+
+    /// compute the aggregate `op` of key entries in `keys`, which are names of key entries or
+    /// patterns in form of `"prefix*"`, e.g. `"temp:2026-10-*"`.
+    pub async fn aggregate(
+        &self,
+        op: AggregateOps,
+        keys: &[&str],
+    ) -> Result<BigDecimal, ClientError> {
+        let params = keys.iter().map(|key| key.to_string()).collect();
+        let result = self.call(Method::Aggregate(op), params).await?;
+        decimal(result)
+    }
+
+    /// compute `key op rhs` then store the result into `key` atomically, and return the new
+    /// value.
+    pub async fn assign(
+        &self,
+        op: BinaryOps,
+        key: &str,
+        rhs: Operand<'_>,
+    ) -> Result<BigDecimal, ClientError> {
+        let result = self
+            .call(Method::Assign(op), vec![key.to_string(), rhs.into()])
+            .await?;
+        decimal(result)
+    }
+
+    async fn binary(
+        &self,
+        op: BinaryOps,
+        key: &str,
+        rhs: Operand<'_>,
+    ) -> Result<BigDecimal, ClientError> {
+        let result = self
+            .call(Method::Binary(op), vec![key.to_string(), rhs.into()])
+            .await?;
+        decimal(result)
+    }
+
+    async fn test(
+        &self,
+        method: Method,
+        key: &str,
+        rhs: Option<Operand<'_>>,
+    ) -> Result<bool, ClientError> {
+        let mut params = vec![key.to_string()];
+        params.extend(rhs.map(String::from));
+        let result = self.call(method, params).await?;
+        Ok(serde_json::from_value(result)?)
+    }
+}
+
+/// parse the decimal number represented by JSON string in "result" member.
+fn decimal(result: Value) -> Result<BigDecimal, ClientError> {
+    let literal: String = serde_json::from_value(result)?;
+    Ok(BigDecimal::from_str(&literal)?)
+}
+
+/// flatten pairs of key and number into params.
+fn pairs(entries: &[(&str, BigDecimal)]) -> Vec<String> {
+    entries
+        .iter()
+        .flat_map(|(key, value)| [key.to_string(), value.to_string()])
+        .collect()
+}
+
+/// compose `cursor` and `limit` option params of scanning methods.
+fn page_options(cursor: Option<&str>, limit: Option<usize>) -> Vec<String> {
+    let mut params = Vec::with_capacity(2);
+    params.extend(cursor.map(|cursor| format!("cursor={cursor}")));
+    params.extend(limit.map(|limit| format!("limit={limit}")));
+    params
+}
+
+/// parse JSON result of scanning methods into [`Page`].
+///
+/// [`Page`]: crate::database::Page
+fn page(result: Value) -> Result<Page, ClientError> {
+    #[derive(serde::Deserialize)]
+    struct RawPage {
+        entries: Vec<(String, String)>,
+        cursor: Option<String>,
+    }
+
+    let raw: RawPage = serde_json::from_value(result)?;
+    let mut entries = Vec::with_capacity(raw.entries.len());
+    for (key, value) in raw.entries {
+        entries.push((key.into_boxed_str(), BigDecimal::from_str(&value)?));
+    }
+
+    Ok(Page {
+        entries,
+        cursor: raw.cursor.map(String::into_boxed_str),
+    })
+}
+
+impl Client {
+    /// send `payload` then wait for JSON response, resend the same `payload` following the
+    /// retry policy on timeout.
+    async fn send_with_retry(
+        &self,
+        payload: &[u8],
+        mut rx: oneshot::Receiver<RespBody>,
+        duration: Duration,
+    ) -> Result<RespBody, ClientError> {
+        let policy = &self.retry_policy;
+        let mut backoff = policy.initial_backoff;
+        for attempt in 0..=policy.max_retries {
+            if attempt > 0 {
+                // keep listening to late response of previous attempts during backoff.
+                if let Ok(resp_body) = timeout(backoff, &mut rx).await {
+                    return resp_body.map_err(|_| ClientError::Disconnected);
+                }
+                backoff = (backoff * policy.multiplier).min(policy.max_backoff);
+                warn!(
+                    "resending JSON request, attempt {attempt}/{}",
+                    policy.max_retries
+                );
+            }
+            self.socket.send(payload).await?;
+            match timeout(duration, &mut rx).await {
+                Ok(Ok(resp_body)) => return Ok(resp_body),
+                Ok(Err(_)) => return Err(ClientError::Disconnected),
+                Err(_) => continue,
+            }
+        }
+
+        Err(ClientError::Timeout)
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.recv_task.abort();
+    }
+}
+
+/// receive JSON responses and resolve them to pending requests by `id`.
+async fn receive(socket: Arc<UdpSocket>, pending: Pending, max_datagram_size: usize) {
+    let mut databuf = vec![0_u8; max_datagram_size];
+    loop {
+        let len = match socket.recv(&mut databuf).await {
+            Ok(len) => len,
+            Err(e) => {
+                // e.g. ICMP port unreachable is reported when the server is not running yet.
+                error!("failed to receive UDP datagram, reason: {e}");
+                continue;
+            }
+        };
+        match frame::decode::<RespBody>(&databuf[..len]) {
+            Ok(resp_body) => match pending.lock().unwrap().remove(&resp_body.id) {
+                Some(tx) => {
+                    // the caller is gone if sending failed, nothing left to resolve.
+                    let _ = tx.send(resp_body);
+                }
+                None => warn!("discard JSON response of unknown ID: {}", resp_body.id),
+            },
+            Err(e) => error!("unrecognisable datagram payload, reason: {e}"),
+        }
+    }
+}
@@ -1,13 +1,33 @@
-use crate::{error::ServerError, BinaryOps, Method, Param};
+mod formula;
 
+use crate::expression::Expr;
+use crate::{error::ServerError, AggregateOps, BinaryOps, MathContext, Method, Param};
+use formula::{Formula, Formulas};
+
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::iter;
+use std::ops::Bound;
 use std::path::{Path, PathBuf};
 use std::str::{self, FromStr};
+use std::sync::{Arc, PoisonError, RwLock};
 
 use bigdecimal::BigDecimal;
 use log::{error, info};
-use sled::{Db, Tree};
+use serde_json::{json, Value};
+use sled::transaction::{
+    ConflictableTransactionError, TransactionError, Transactional, TransactionalTree,
+};
+use sled::{Batch, CompareAndSwapError, Db, IVec, Tree};
 use zerocopy::{AsBytes, ByteSlice};
 
+/// the option name of update and delete methods to return the previous value as result.
+const RETURN_OLD: &str = "return_old";
+/// the number of key entries in a page of scanning methods without `limit` option.
+const DEFAULT_PAGE_LIMIT: usize = 100;
+/// the maximum number of key entries in a page of scanning methods.
+const MAX_PAGE_LIMIT: usize = 1000;
+
 /// The connection pool to maintain [`sled`] database running instance and path prefix to storage
 /// file.
 ///
@@ -15,6 +35,7 @@ use zerocopy::{AsBytes, ByteSlice};
 pub struct ConnectionPool {
     prefix: PathBuf,
     db: Db,
+    definitions: Arc<RwLock<()>>,
 }
 
 impl ConnectionPool {
@@ -29,16 +50,30 @@ impl ConnectionPool {
         let prefix = path.as_ref().to_path_buf();
         let db = sled::open(path)?;
 
-        Ok(ConnectionPool { prefix, db })
+        Ok(ConnectionPool {
+            prefix,
+            db,
+            definitions: Arc::default(),
+        })
     }
 
     /// open user storage tree by provided `user token`.
     pub fn open_user_database(&self, token: impl ByteSlice) -> Result<UserDatabase, ServerError> {
         let tree = self.db.open_tree(token.as_bytes())?;
+        let formulas = self
+            .db
+            .open_tree([token.as_bytes(), b"/formulas"].concat())?;
+        let dependents = self
+            .db
+            .open_tree([token.as_bytes(), b"/dependents"].concat())?;
 
         Ok(UserDatabase {
             token: token.to_vec(),
             tree,
+            formulas,
+            dependents,
+            definitions: Arc::clone(&self.definitions),
+            context: MathContext::default(),
         })
     }
 }
@@ -48,9 +83,45 @@ impl ConnectionPool {
 ///
 /// TODO: implement Token instance which can be used to generate user-token for individial access
 /// to data storage in frontend.
+///
+/// NOTE:
+///     - derived key entries defined by [`Method::Define`] are kept in separate trees of their
+///     formulas and reverse dependencies, writes to their dependencies are recomputing them in
+///     the same transaction.
+///     - writes to other key entries are applied to the tree directly, they share a lock with
+///     each other, which is held exclusively by transactions, so no formula is defined between
+///     checking the key entry and writing it.
+///
+/// [`Method::Define`]: crate::Method::Define
 pub struct UserDatabase {
     token: Vec<u8>,
     tree: Tree,
+    formulas: Tree,
+    dependents: Tree,
+    definitions: Arc<RwLock<()>>,
+    context: MathContext,
+}
+
+/// A page of key entries in ascending order of keys returned by [`UserDatabase::list`],
+/// [`UserDatabase::scan_prefix`] and [`UserDatabase::range`].
+#[derive(Debug, Clone)]
+pub struct Page {
+    /// pairs of key and number of key entries.
+    pub entries: Vec<(Box<str>, BigDecimal)>,
+    /// the key of last entry in page if more entries remain, which is passed as `cursor` to
+    /// fetch the next page.
+    pub cursor: Option<Box<str>>,
+}
+
+impl From<Page> for Value {
+    fn from(page: Page) -> Self {
+        let entries: Vec<Value> = page
+            .entries
+            .into_iter()
+            .map(|(key, value)| json!([key, value.to_string()]))
+            .collect();
+        json!({ "entries": entries, "cursor": page.cursor })
+    }
 }
 
 impl UserDatabase {
@@ -59,159 +130,1600 @@ impl UserDatabase {
         &self.token
     }
 
+    /// get the default [`MathContext`] applied to invocations on user database.
+    ///
+    /// [`MathContext`]: crate::MathContext
+    pub fn get_math_context(&self) -> &MathContext {
+        &self.context
+    }
+
+    /// set the default [`MathContext`] applied to invocations without `precision` and
+    /// `rounding` option params.
+    ///
+    /// [`MathContext`]: crate::MathContext
+    pub fn with_math_context(mut self, context: MathContext) -> Self {
+        self.context = context;
+        self
+    }
+
     /// perform ACID transactions by provided [`Method`] and [`Param`]s from JSON Request body,
     /// and return the result of invocation for JSON Response "result" and "error" object members.
     ///
+    /// NOTE: decimal numbers in result are represented by JSON strings to preserve precision.
+    ///
     /// [`Method`]: crate::Method
     /// [`Param`]: crate::Param
     pub fn transaction(
         &self,
         method: Method,
         params: Vec<Param>,
-    ) -> Result<Option<String>, ServerError> {
-        // resolve values from Params
-        let mut param_iter = params.into_iter();
-        let key = match param_iter.next() {
-            Some(Param::Name(literal)) => literal,
-            Some(_) => {
-                let e = ServerError::MissingName(0);
-                error!("{e}");
-                return Err(e);
-            }
-            None => {
-                let e = ServerError::MissingParam(1);
-                error!("{e}");
-                return Err(e);
+    ) -> Result<Option<Value>, ServerError> {
+        let serializable = match (&method, written_keys(&method, &params)) {
+            // `TransactionalTree` does not support iteration.
+            (Method::Aggregate(op), _) => {
+                return match self.aggregate(op, params) {
+                    Ok(res) => Ok(Some(Value::String(res.to_string()))),
+                    Err(e) => {
+                        error!("{e}");
+                        Err(self.explain(e))
+                    }
+                };
             }
-        };
-
-        let result = match method {
-            Method::Create => match param_iter.next() {
-                Some(Param::Number(value)) => match self.create(&key, value) {
-                    Ok(_) => Ok(None),
+            (Method::List | Method::ScanPrefix | Method::Range, _) => {
+                return match self.scan(method, params) {
+                    Ok(page) => Ok(Some(page.into())),
                     Err(e) => {
                         error!("{e}");
                         Err(e)
                     }
+                };
+            }
+            // resolve every key entry from the same snapshot, and check every key entry before
+            // writing batch.
+            (
+                Method::Eval
+                | Method::Define
+                | Method::MultiRead
+                | Method::MultiCreate
+                | Method::MultiUpdate,
+                _,
+            ) => true,
+            (_, keys) if keys.is_empty() => false,
+            // recompute derived key entries in the same transaction.
+            (_, keys) => {
+                let _shared = self
+                    .definitions
+                    .read()
+                    .unwrap_or_else(PoisonError::into_inner);
+                let mut derived = false;
+                for key in &keys {
+                    derived = derived
+                        || self.formulas.contains_key(key.as_bytes())?
+                        || self.dependents.contains_key(key.as_bytes())?;
+                }
+                if !derived {
+                    return execute(&self.tree, method, params, &self.context)
+                        .map_err(|e| self.explain(e));
+                }
+                true
+            }
+        };
+        let result = if serializable {
+            self.serializable(|tx| invoke(tx, method.clone(), params.clone(), &self.context))
+        } else {
+            execute(&self.tree, method, params, &self.context)
+        };
+
+        result.map_err(|e| self.explain(e))
+    }
+
+    /// perform a sequence of [`Method`] invocations with their [`Param`]s inside a single
+    /// `sled` transaction, and return the results in the same order of `operations`.
+    ///
+    /// NOTE:
+    ///     - the batch is all-or-nothing, a failure from any operation rolls back every
+    ///     operation applied before it and returns [`ServerError::AtomicBatch`] with the index
+    ///     of the failed operation.
+    ///     - operations are observing the writes of preceding operations in the same batch.
+    ///     - scanning and aggregate methods are rejected with [`ServerError::NotAtomic`] since
+    ///     `sled` transaction does not support iteration.
+    ///
+    /// [`Method`]: crate::Method
+    /// [`Param`]: crate::Param
+    /// [`ServerError::AtomicBatch`]: crate::error::ServerError::AtomicBatch
+    /// [`ServerError::NotAtomic`]: crate::error::ServerError::NotAtomic
+    pub fn atomic_transaction(
+        &self,
+        operations: Vec<(Method, Vec<Param>)>,
+    ) -> Result<Vec<Option<Value>>, ServerError> {
+        let results = self
+            .serializable(|tx| {
+                let mut results = Vec::with_capacity(operations.len());
+                for (index, (method, params)) in operations.iter().enumerate() {
+                    match invoke(tx, method.clone(), params.clone(), &self.context) {
+                        Ok(res) => results.push(res),
+                        Err(e @ ServerError::SledTransaction(_)) => return Err(e),
+                        Err(e) => {
+                            return Err(ServerError::AtomicBatch {
+                                index,
+                                reason: Box::new(e),
+                            })
+                        }
+                    }
+                }
+
+                Ok(results)
+            })
+            .map_err(|e| self.explain(e))?;
+        info!("atomic batch of {} operations committed.", operations.len());
+
+        Ok(results)
+    }
+
+    /// list key entries after `cursor` in ascending order of keys, at most `limit` entries.
+    pub fn list(&self, cursor: Option<&str>, limit: usize) -> Result<Page, ServerError> {
+        let lower = match cursor {
+            Some(cursor) => Bound::Excluded(cursor.as_bytes()),
+            None => Bound::Unbounded,
+        };
+        page(
+            self.tree.range::<&[u8], _>((lower, Bound::Unbounded)),
+            limit,
+        )
+    }
+
+    /// list key entries beginning with `prefix` after `cursor` in ascending order of keys, at
+    /// most `limit` entries.
+    pub fn scan_prefix(
+        &self,
+        prefix: &str,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<Page, ServerError> {
+        match cursor {
+            Some(cursor) if cursor >= prefix => {
+                let entries = self
+                    .tree
+                    .range::<&[u8], _>((Bound::Excluded(cursor.as_bytes()), Bound::Unbounded))
+                    .take_while(|entry| match entry {
+                        Ok((key, _)) => key.starts_with(prefix.as_bytes()),
+                        Err(_) => true,
+                    });
+                page(entries, limit)
+            }
+            _ => page(self.tree.scan_prefix(prefix.as_bytes()), limit),
+        }
+    }
+
+    /// list key entries from `start` inclusive to `end` exclusive after `cursor` in ascending
+    /// order of keys, at most `limit` entries.
+    pub fn range(
+        &self,
+        start: &str,
+        end: &str,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<Page, ServerError> {
+        let lower = match cursor {
+            Some(cursor) if cursor >= start => Bound::Excluded(cursor.as_bytes()),
+            _ => Bound::Included(start.as_bytes()),
+        };
+        let empty = match lower {
+            Bound::Excluded(lower) => lower >= end.as_bytes(),
+            _ => start >= end,
+        };
+        if empty {
+            return page(std::iter::empty(), limit);
+        }
+        page(
+            self.tree
+                .range::<&[u8], _>((lower, Bound::Excluded(end.as_bytes()))),
+            limit,
+        )
+    }
+
+    /// resolve positional params and `cursor`, `limit` options of scanning `method`.
+    fn scan(&self, method: Method, params: Vec<Param>) -> Result<Page, ServerError> {
+        let mut cursor: Option<Box<str>> = None;
+        let mut limit = DEFAULT_PAGE_LIMIT;
+        let mut names = Vec::with_capacity(params.len());
+        for param in params {
+            match param {
+                Param::Name(name) => names.push(name),
+                Param::Number(_) => return Err(ServerError::MissingName(names.len())),
+                Param::Option { name, value } => match &*name {
+                    "cursor" => cursor = Some(value),
+                    "limit" => match value.parse::<usize>() {
+                        // the page is capped at `MAX_PAGE_LIMIT` entries.
+                        Ok(n) if n > 0 => limit = n.min(MAX_PAGE_LIMIT),
+                        _ => return Err(ServerError::InvalidOption { name, value }),
+                    },
+                    _ => return Err(ServerError::InvalidOption { name, value }),
                 },
-                Some(_) => Err(ServerError::MissingNumber(1)),
+            }
+        }
+
+        let cursor = cursor.as_deref();
+        match method {
+            Method::List => self.list(cursor, limit),
+            Method::ScanPrefix => match names.first() {
+                Some(prefix) => self.scan_prefix(prefix, cursor, limit),
                 None => Err(ServerError::MissingParam(1)),
             },
-            Method::Read => match self.fetch(&key) {
-                Ok(decimal) => Ok(Some(decimal.to_string())),
+            Method::Range => match (names.first(), names.get(1)) {
+                (Some(start), Some(end)) => self.range(start, end, cursor, limit),
+                (Some(_), None) => Err(ServerError::MissingParam(1)),
+                (None, _) => Err(ServerError::MissingParam(2)),
+            },
+            method => Err(ServerError::MethodNotFound(method.to_string().into())),
+        }
+    }
+
+    /// compute the aggregate `op` of key entries in `params`, which are names of key entries or
+    /// patterns in form of `"prefix*"` matching every key entry beginning with `prefix`.
+    ///
+    /// NOTE:
+    ///     - key entries are counted once even if they are listed or matched repeatedly.
+    ///     - a named key entry MUST exist.
+    ///     - matched and named key entries are read in one pass while excluding every write to
+    ///     user databases, so the result is computed from one consistent view.
+    fn aggregate(&self, op: &AggregateOps, params: Vec<Param>) -> Result<BigDecimal, ServerError> {
+        let mut ctx = self.context;
+        let mut named = BTreeSet::new();
+        let mut prefixes = BTreeSet::new();
+        for (idx, param) in params.into_iter().enumerate() {
+            match param {
+                Param::Name(name) => match name.strip_suffix('*') {
+                    Some(prefix) => {
+                        prefixes.insert(Box::<str>::from(prefix));
+                    }
+                    None => {
+                        named.insert(name);
+                    }
+                },
+                Param::Number(_) => return Err(ServerError::MissingName(idx)),
+                Param::Option { name, value } => ctx.set_option(&name, &value)?,
+            }
+        }
+        if named.is_empty() && prefixes.is_empty() {
+            return Err(ServerError::MissingParam(1));
+        }
+
+        let mut entries = BTreeMap::new();
+        {
+            // writes outside transactions share the lock which transactions hold exclusively.
+            let _exclusive = self
+                .definitions
+                .write()
+                .unwrap_or_else(PoisonError::into_inner);
+            for prefix in &prefixes {
+                for entry in self.tree.scan_prefix(prefix.as_bytes()) {
+                    let (key, value) = entry?;
+                    if let Entry::Vacant(entry) = entries.entry(key) {
+                        entry.insert(parse_value(&value)?);
+                    }
+                }
+            }
+            for key in &named {
+                if !entries.contains_key(key.as_bytes()) {
+                    entries.insert(key.as_bytes().into(), self.tree.fetch(key)?);
+                }
+            }
+        }
+        let values: Vec<BigDecimal> = entries.into_values().collect();
+        info!(
+            "performing aggregate operation, method = {}, count = {}",
+            op,
+            values.len()
+        );
+
+        op.apply(&values, &ctx)
+    }
+
+    /// run `f` inside a `sled` transaction over user database trees, which is retried on
+    /// conflict and rolled back on error.
+    ///
+    /// NOTE: the transaction excludes writes outside transaction, which check that the key
+    /// entry is neither derived nor depended on before writing it.
+    fn serializable<T, F>(&self, f: F) -> Result<T, ServerError>
+    where
+        F: Fn(&Formulas) -> Result<T, ServerError>,
+    {
+        let _exclusive = self
+            .definitions
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        let trees = (&self.tree, &self.formulas, &self.dependents);
+        let result = trees.transaction(|(values, formulas, dependents)| {
+            let tx = Formulas {
+                values,
+                formulas,
+                dependents,
+            };
+            match f(&tx) {
+                Ok(res) => Ok(res),
+                // let `sled` retry the transaction on conflict.
+                Err(ServerError::SledTransaction(e)) => Err(e.into()),
+                Err(e) => Err(ConflictableTransactionError::Abort(e)),
+            }
+        });
+
+        match result {
+            Ok(res) => Ok(res),
+            Err(TransactionError::Abort(e)) => {
+                error!("{e}");
+                Err(e)
+            }
+            Err(TransactionError::Storage(e)) => Err(e.into()),
+        }
+    }
+
+    /// replace [`ServerError::DbKeyNotFound`] of derived key entry in error state with
+    /// [`ServerError::DerivedKeyUnresolved`].
+    ///
+    /// [`ServerError::DbKeyNotFound`]: crate::error::ServerError::DbKeyNotFound
+    /// [`ServerError::DerivedKeyUnresolved`]: crate::error::ServerError::DerivedKeyUnresolved
+    fn explain(&self, e: ServerError) -> ServerError {
+        match e {
+            ServerError::DbKeyNotFound(key) => {
+                let formula = self
+                    .formulas
+                    .get(key.as_bytes())
+                    .ok()
+                    .flatten()
+                    .and_then(|bytes| serde_json::from_slice::<Formula>(&bytes).ok());
+                match formula {
+                    Some(Formula {
+                        error: Some(reason),
+                        ..
+                    }) => ServerError::DerivedKeyUnresolved {
+                        key,
+                        reason: reason.into(),
+                    },
+                    _ => ServerError::DbKeyNotFound(key),
+                }
+            }
+            ServerError::AtomicBatch { index, reason } => ServerError::AtomicBatch {
+                index,
+                reason: Box::new(self.explain(*reason)),
+            },
+            e => e,
+        }
+    }
+}
+
+/// invoke `method` inside transaction `tx`, then recompute derived key entries depending on
+/// the written key entry.
+fn invoke(
+    tx: &Formulas,
+    method: Method,
+    params: Vec<Param>,
+    ctx: &MathContext,
+) -> Result<Option<Value>, ServerError> {
+    // `TransactionalTree` does not support iteration.
+    if matches!(
+        method,
+        Method::List | Method::ScanPrefix | Method::Range | Method::Aggregate(_)
+    ) {
+        return Err(ServerError::NotAtomic(method.to_string().into()));
+    }
+    if let Method::Define = method {
+        let (key, value) = define(tx, params, ctx)?;
+        tx.propagate(&key, ctx)?;
+        return Ok(Some(Value::String(value.to_string())));
+    }
+
+    let keys = written_keys(&method, &params);
+    for key in &keys {
+        if tx.get(key)?.is_none() {
+            continue;
+        }
+        if let Method::Delete = method {
+            // deleting derived key entry removes its formula, the value is absent in error
+            // state.
+            tx.undefine(key)?;
+            let deleted = Storage::remove(tx.values, key)?;
+            info!("[\"{key}\"] entry has been deleted from user database.");
+            tx.propagate(key, ctx)?;
+            return match deleted {
+                Some(deleted) if return_old(&method, &params)? => {
+                    Ok(Some(Value::String(parse_value(&deleted)?.to_string())))
+                }
+                _ => Ok(None),
+            };
+        }
+        let e = ServerError::DerivedKeyReadOnly(key.clone());
+        error!("{e}");
+        return Err(e);
+    }
+
+    let res = execute(tx.values, method, params, ctx)?;
+    for key in &keys {
+        tx.propagate(key, ctx)?;
+    }
+
+    Ok(res)
+}
+
+/// define derived key entry at the first of `params` by the expression at the second of
+/// `params`, and return the key with its value.
+fn define(
+    tx: &Formulas,
+    params: Vec<Param>,
+    ctx: &MathContext,
+) -> Result<(Box<str>, BigDecimal), ServerError> {
+    let mut param_iter = params.into_iter();
+    let key = match param_iter.next() {
+        Some(Param::Name(key)) => key,
+        Some(_) => return Err(ServerError::MissingName(0)),
+        None => return Err(ServerError::MissingParam(2)),
+    };
+    let expression = match param_iter.next() {
+        Some(Param::Name(expression)) => expression.to_string(),
+        Some(Param::Number(number)) => number.to_string(),
+        // formulas are always recomputed with the default context of user database.
+        Some(Param::Option { name, value }) => {
+            return Err(ServerError::InvalidOption { name, value })
+        }
+        None => return Err(ServerError::MissingParam(1)),
+    };
+    if let Some(Param::Option { name, value }) = param_iter.next() {
+        return Err(ServerError::InvalidOption { name, value });
+    }
+    let value = tx.define(&key, &expression, ctx)?;
+
+    Ok((key, value))
+}
+
+/// test whether `return_old` option in `params` is set to `true`, which is only accepted by
+/// [`Method::Update`] and [`Method::Delete`].
+///
+/// [`Method::Update`]: crate::Method::Update
+/// [`Method::Delete`]: crate::Method::Delete
+fn return_old(method: &Method, params: &[Param]) -> Result<bool, ServerError> {
+    let mut return_old = false;
+    for param in params {
+        match param {
+            Param::Option { name, value } if &**name == RETURN_OLD => {
+                return_old = match (method, value.parse::<bool>()) {
+                    (Method::Update | Method::Delete, Ok(flag)) => flag,
+                    _ => {
+                        return Err(ServerError::InvalidOption {
+                            name: name.clone(),
+                            value: value.clone(),
+                        })
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    Ok(return_old)
+}
+
+/// get the key entries written by `method`, which are the target key entry of [`Method::Eval`],
+/// the keys of pairs in `params` for [`Method::MultiCreate`] and [`Method::MultiUpdate`], or
+/// the first of `params` for other writing methods.
+///
+/// [`Method::Eval`]: crate::Method::Eval
+/// [`Method::MultiCreate`]: crate::Method::MultiCreate
+/// [`Method::MultiUpdate`]: crate::Method::MultiUpdate
+fn written_keys(method: &Method, params: &[Param]) -> Vec<Box<str>> {
+    let mut names = params
+        .iter()
+        .filter(|param| !matches!(param, Param::Option { .. }));
+    let written: Vec<&Param> = match method {
+        Method::Create
+        | Method::Update
+        | Method::Set
+        | Method::Delete
+        | Method::Assign(_)
+        | Method::Increment
+        | Method::Decrement
+        | Method::Cas => names.next().into_iter().collect(),
+        Method::Eval => names.nth(1).into_iter().collect(),
+        Method::MultiCreate | Method::MultiUpdate => names.step_by(2).collect(),
+        _ => vec![],
+    };
+    written
+        .into_iter()
+        .filter_map(|param| match param {
+            Param::Name(key) => Some(key.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// collect at most `limit` key entries from `entries` into [`Page`], the cursor is set if more
+/// entries remain.
+fn page<I>(entries: I, limit: usize) -> Result<Page, ServerError>
+where
+    I: Iterator<Item = sled::Result<(IVec, IVec)>>,
+{
+    let mut page = Page {
+        entries: Vec::with_capacity(limit),
+        cursor: None,
+    };
+    for entry in entries {
+        let (key, value) = entry?;
+        if page.entries.len() == limit {
+            page.cursor = page.entries.last().map(|(key, _)| key.clone());
+            break;
+        }
+        page.entries
+            .push((str::from_utf8(&key)?.into(), parse_value(&value)?));
+    }
+    info!("scan {} key entries.", page.entries.len());
+
+    Ok(page)
+}
+
+/// resolve values from `params` then invoke `method` on `storage`, the option params override
+/// the default `context` for this invocation.
+fn execute<S: Storage>(
+    storage: &S,
+    method: Method,
+    params: Vec<Param>,
+    context: &MathContext,
+) -> Result<Option<Value>, ServerError> {
+    // separate option params from positional params
+    let mut ctx = *context;
+    let return_old = match return_old(&method, &params) {
+        Ok(return_old) => return_old,
+        Err(e) => {
+            error!("{e}");
+            return Err(e);
+        }
+    };
+    let mut positional = Vec::with_capacity(params.len());
+    for param in params {
+        match param {
+            Param::Option { name, .. } if &*name == RETURN_OLD => (),
+            Param::Option { name, value } => {
+                if let Err(e) = ctx.set_option(&name, &value) {
+                    error!("{e}");
+                    return Err(e);
+                }
+            }
+            other => positional.push(other),
+        }
+    }
+
+    // resolve values from Params
+    let mut param_iter = positional.into_iter();
+    let key = match param_iter.next() {
+        Some(Param::Name(literal)) => literal,
+        // the expression of `eval` is the first param, which may be a number itself.
+        Some(Param::Number(number)) if matches!(method, Method::Eval) => {
+            number.to_string().into_boxed_str()
+        }
+        Some(_) => {
+            let e = ServerError::MissingName(0);
+            error!("{e}");
+            return Err(e);
+        }
+        None => {
+            let e = ServerError::MissingParam(1);
+            error!("{e}");
+            return Err(e);
+        }
+    };
+
+    let result = match method {
+        Method::Create => match param_iter.next() {
+            Some(Param::Number(value)) => match storage.create(&key, ctx.round(value)) {
+                Ok(_) => Ok(None),
                 Err(e) => {
                     error!("{e}");
                     Err(e)
                 }
             },
-            Method::Update => match param_iter.next() {
-                Some(Param::Number(new_value)) => match self.update(&key, new_value) {
-                    Ok(_) => Ok(None),
-                    Err(e) => {
-                        error!("{e}");
-                        Err(e)
-                    }
-                },
-                Some(_) => Err(ServerError::MissingNumber(1)),
-                None => Err(ServerError::MissingParam(1)),
-            },
-            Method::Delete => match self.delete(&key) {
+            Some(_) => Err(ServerError::MissingNumber(1)),
+            None => Err(ServerError::MissingParam(1)),
+        },
+        Method::Read => match storage.fetch(&key) {
+            Ok(decimal) => Ok(Some(Value::String(decimal.to_string()))),
+            Err(e) => {
+                error!("{e}");
+                Err(e)
+            }
+        },
+        Method::Update => match param_iter.next() {
+            Some(Param::Number(new_value)) => match storage.update(&key, ctx.round(new_value)) {
+                Ok(old_value) if return_old => Ok(Some(Value::String(old_value.to_string()))),
                 Ok(_) => Ok(None),
                 Err(e) => {
-                    // print error message of custom DbKeyNotFound error.
                     error!("{e}");
                     Err(e)
                 }
             },
-            Method::Binary(op) => match self.fetch(&key) {
-                Ok(left_value) => match param_iter.next() {
-                    Some(Param::Name(second_key)) => match self.fetch(&second_key) {
-                        Ok(right_value) => {
-                            let res = match op {
-                                BinaryOps::Add => left_value + right_value,
-                                BinaryOps::Subtract => left_value - right_value,
-                                BinaryOps::Multiply => left_value * right_value,
-                                BinaryOps::Divide => left_value / right_value,
-                            };
-
-                            Ok(Some(res.to_string()))
-                        }
+            Some(_) => Err(ServerError::MissingNumber(1)),
+            None => Err(ServerError::MissingParam(1)),
+        },
+        Method::Set => match param_iter.next() {
+            Some(Param::Number(value)) => match storage.set(&key, ctx.round(value)) {
+                Ok(previous) => Ok(Some(json!({
+                    "inserted": previous.is_none(),
+                    "previous": previous.map(|previous| previous.to_string()),
+                }))),
+                Err(e) => {
+                    error!("{e}");
+                    Err(e)
+                }
+            },
+            Some(_) => Err(ServerError::MissingNumber(1)),
+            None => Err(ServerError::MissingParam(1)),
+        },
+        Method::Delete => match storage.delete(&key) {
+            Ok(deleted) if return_old => Ok(Some(Value::String(deleted.to_string()))),
+            Ok(_) => Ok(None),
+            Err(e) => {
+                // print error message of custom DbKeyNotFound error.
+                error!("{e}");
+                Err(e)
+            }
+        },
+        Method::Binary(op) => match storage.fetch(&key) {
+            Ok(left_value) => match resolve(storage, param_iter.next(), 1) {
+                Ok(right_value) => {
+                    info!(
+                        "performing binary operation, method = {}, LHS = {}, RHS = {}",
+                        op, left_value, right_value
+                    );
+                    match op.apply(&left_value, &right_value, &ctx) {
+                        Ok(res) => Ok(Some(Value::String(res.to_string()))),
                         Err(e) => {
                             error!("{e}");
                             Err(e)
                         }
-                    },
-                    Some(Param::Number(right_value)) => {
-                        info!(
-                            "performing binary operation, method = {}, LHS = {}, RHS = {}",
-                            op,
-                            left_value.to_string(),
-                            right_value.to_string()
-                        );
-                        let res = match op {
-                            BinaryOps::Add => left_value + right_value,
-                            BinaryOps::Subtract => left_value - right_value,
-                            BinaryOps::Multiply => left_value * right_value,
-                            BinaryOps::Divide => left_value / right_value,
-                        };
-                        Ok(Some(res.to_string()))
                     }
-                    None => Err(ServerError::MissingParam(1)),
-                },
-                Err(e) => Err(e),
+                }
+                Err(e) => {
+                    error!("{e}");
+                    Err(e)
+                }
             },
+            Err(e) => Err(e),
+        },
+        Method::Compare(op) => match storage.fetch(&key) {
+            Ok(left_value) => match resolve(storage, param_iter.next(), 1) {
+                Ok(right_value) => Ok(Some(op.apply(&left_value, &right_value))),
+                Err(e) => {
+                    error!("{e}");
+                    Err(e)
+                }
+            },
+            Err(e) => Err(e),
+        },
+        Method::Predicate(op) => match storage.fetch(&key) {
+            Ok(value) => Ok(Some(Value::Bool(op.apply(&value)))),
+            Err(e) => Err(e),
+        },
+        Method::Unary(op) => match storage.fetch(&key) {
+            Ok(value) => {
+                info!(
+                    "performing unary operation, method = {}, value = {}",
+                    op, value
+                );
+                match op.apply(&value, &ctx) {
+                    Ok(res) => Ok(Some(Value::String(res.to_string()))),
+                    Err(e) => {
+                        error!("{e}");
+                        Err(e)
+                    }
+                }
+            }
+            Err(e) => Err(e),
+        },
+        Method::Assign(op) => match resolve(storage, param_iter.next(), 1) {
+            Ok(right_value) => {
+                match storage
+                    .update_with(&key, |left_value| op.apply(left_value, &right_value, &ctx))
+                {
+                    Ok(new_value) => Ok(Some(Value::String(new_value.to_string()))),
+                    Err(e) => {
+                        error!("{e}");
+                        Err(e)
+                    }
+                }
+            }
+            Err(e) => {
+                error!("{e}");
+                Err(e)
+            }
+        },
+        Method::Increment | Method::Decrement => {
+            let amount = match param_iter.next() {
+                Some(param) => resolve(storage, Some(param), 1),
+                None => Ok(BigDecimal::from(1)),
+            };
+            match amount {
+                Ok(amount) => {
+                    let delta = match method {
+                        Method::Decrement => -amount,
+                        _ => amount,
+                    };
+                    match storage.increment(&key, &delta, &ctx) {
+                        Ok(new_value) => Ok(Some(Value::String(new_value.to_string()))),
+                        Err(e) => {
+                            error!("{e}");
+                            Err(e)
+                        }
+                    }
+                }
+                Err(e) => {
+                    error!("{e}");
+                    Err(e)
+                }
+            }
+        }
+        Method::Cas => match (param_iter.next(), param_iter.next()) {
+            (Some(Param::Number(expected)), Some(Param::Number(new_value))) => {
+                match storage.compare_and_set(&key, &expected, ctx.round(new_value)) {
+                    Ok(_) => Ok(None),
+                    Err(e) => {
+                        error!("{e}");
+                        Err(e)
+                    }
+                }
+            }
+            (Some(Param::Number(_)), Some(_)) => Err(ServerError::MissingNumber(2)),
+            (Some(Param::Number(_)), None) => Err(ServerError::MissingParam(1)),
+            (Some(_), _) => Err(ServerError::MissingNumber(1)),
+            (None, _) => Err(ServerError::MissingParam(2)),
+        },
+        // multi-key methods take every positional param as key entry or pair of key and number.
+        Method::MultiRead => {
+            let params = iter::once(Param::Name(key)).chain(param_iter).collect();
+            match multi_read(storage, params) {
+                Ok(values) => Ok(Some(values)),
+                Err(e) => {
+                    error!("{e}");
+                    Err(e)
+                }
+            }
+        }
+        Method::MultiCreate | Method::MultiUpdate => {
+            let params = iter::once(Param::Name(key)).chain(param_iter).collect();
+            match multi_write(storage, &method, params, &ctx) {
+                Ok(_) => Ok(None),
+                Err(e) => {
+                    error!("{e}");
+                    Err(e)
+                }
+            }
+        }
+        // key entries are scanned by `UserDatabase::scan` and `UserDatabase::aggregate` outside
+        // transaction, and rejected by `invoke` inside transaction.
+        Method::List | Method::ScanPrefix | Method::Range | Method::Aggregate(_) => {
+            Err(ServerError::NotAtomic(method.to_string().into()))
+        }
+        // derived key entries are defined by `invoke` inside transaction.
+        Method::Define => Err(ServerError::MethodNotFound(method.to_string().into())),
+        // the first param is the expression instead of key.
+        Method::Eval => match eval(storage, &key, param_iter.next(), &ctx) {
+            Ok(res) => Ok(Some(Value::String(res.to_string()))),
+            Err(e) => {
+                error!("{e}");
+                Err(e)
+            }
+        },
+    };
+
+    result
+}
+
+/// read every key entry in `params` from `storage` into JSON object, the value of missing key
+/// entry is `null`.
+fn multi_read<S: Storage>(storage: &S, params: Vec<Param>) -> Result<Value, ServerError> {
+    let mut values = serde_json::Map::with_capacity(params.len());
+    for (idx, param) in params.into_iter().enumerate() {
+        let key = match param {
+            Param::Name(key) => key,
+            _ => return Err(ServerError::MissingName(idx)),
+        };
+        let value = match storage.get(&key)? {
+            Some(bytes) => Value::String(parse_value(&bytes)?.to_string()),
+            None => Value::Null,
         };
+        values.insert(key.into(), value);
+    }
+    info!("fetch {} key entries.", values.len());
+
+    Ok(Value::Object(values))
+}
+
+/// write pairs of key and number in `params` into `storage` by a single `sled::Batch`, the key
+/// entries MUST NOT exist for [`Method::MultiCreate`] and MUST exist for
+/// [`Method::MultiUpdate`].
+///
+/// [`Method::MultiCreate`]: crate::Method::MultiCreate
+/// [`Method::MultiUpdate`]: crate::Method::MultiUpdate
+fn multi_write<S: Storage>(
+    storage: &S,
+    method: &Method,
+    params: Vec<Param>,
+    ctx: &MathContext,
+) -> Result<(), ServerError> {
+    let mut batch = Batch::default();
+    let mut pending: HashMap<Box<str>, String> = HashMap::new();
+    let mut param_iter = params.into_iter().enumerate();
+    while let Some((idx, param)) = param_iter.next() {
+        let key = match param {
+            Param::Name(key) => key,
+            _ => return Err(ServerError::MissingName(idx)),
+        };
+        let float_string = match param_iter.next() {
+            Some((_, Param::Number(value))) => ctx.round(value).to_string(),
+            Some((idx, _)) => return Err(ServerError::MissingNumber(idx)),
+            None => return Err(ServerError::MissingParam(1)),
+        };
+        let current = match pending.get(&key) {
+            Some(pending) => Some(IVec::from(pending.as_bytes())),
+            None => storage.get(&key)?,
+        };
+        match (method, current) {
+            (Method::MultiCreate, Some(current)) => {
+                return Err(CompareAndSwapError {
+                    current: Some(current),
+                    proposed: Some(float_string.as_bytes().into()),
+                }
+                .into())
+            }
+            (Method::MultiUpdate, None) => return Err(ServerError::DbKeyUpdate(key)),
+            _ => (),
+        }
+        batch.insert(key.as_bytes(), float_string.as_bytes());
+        pending.insert(key, float_string);
+    }
+    storage.apply_batch(batch)?;
+    info!("{method} {} key entries in batch.", pending.len());
 
-        result
+    Ok(())
+}
+
+/// evaluate `expression` with key entries from `storage`, then store the result into `target`
+/// key entry if provided.
+fn eval<S: Storage>(
+    storage: &S,
+    expression: &str,
+    target: Option<Param>,
+    ctx: &MathContext,
+) -> Result<BigDecimal, ServerError> {
+    let result = Expr::from_str(expression)
+        .and_then(|expr| expr.evaluate(&|key: &str| storage.fetch(key), ctx))?;
+    info!("evaluate expression `{expression}` = {result}");
+
+    match target {
+        Some(Param::Name(target)) => {
+            let float_string = result.to_string();
+            storage.insert(&target, &float_string)?;
+            info!("store [\"{target}\"] value: {float_string}");
+        }
+        Some(_) => return Err(ServerError::MissingName(1)),
+        None => (),
     }
 
+    Ok(result)
+}
+
+/// resolve the number of operand `param` at index `idx`, which is either fetched by name of key
+/// entry from `storage` or a literal number.
+fn resolve<S: Storage>(
+    storage: &S,
+    param: Option<Param>,
+    idx: usize,
+) -> Result<BigDecimal, ServerError> {
+    match param {
+        Some(Param::Name(key)) => storage.fetch(&key),
+        Some(Param::Number(number)) => Ok(number),
+        Some(Param::Option { .. }) | None => Err(ServerError::MissingParam(idx)),
+    }
+}
+
+/// parse the number stored as utf8-string in key entry.
+fn parse_value(bytes: &[u8]) -> Result<BigDecimal, ServerError> {
+    let float_string = str::from_utf8(bytes)?;
+    Ok(BigDecimal::from_str(float_string)?)
+}
+
+/// Key-value storage of user database entries, which is implemented by `sled::Tree` for
+/// individual invocation and `TransactionalTree` for atomic batch invocations.
+///
+/// NOTE: CRUD operations are provided on top of `get`, `insert` and `remove` primitives, the
+/// implementors may override them with atomic operations supported by the storage.
+trait Storage {
+    fn get(&self, key: &str) -> Result<Option<IVec>, ServerError>;
+
+    fn insert(&self, key: &str, value: &str) -> Result<Option<IVec>, ServerError>;
+
+    fn remove(&self, key: &str) -> Result<Option<IVec>, ServerError>;
+
+    fn apply_batch(&self, batch: Batch) -> Result<(), ServerError>;
+
     fn create(&self, key: &str, value: BigDecimal) -> Result<(), ServerError> {
         let float_string = value.to_string();
-        match self.tree.compare_and_swap(
-            key.as_bytes(),
-            None as Option<&[u8]>,
-            Some(float_string.as_bytes()),
-        )? {
-            Ok(_) => {
-                info!("create new key entry [\"{key}\"] with number = {float_string}");
-                Ok(())
+        if let Some(current) = self.get(key)? {
+            return Err(CompareAndSwapError {
+                current: Some(current),
+                proposed: Some(float_string.as_bytes().into()),
             }
-            Err(cas) => Err(cas.into()),
+            .into());
         }
+        self.insert(key, &float_string)?;
+        info!("create new key entry [\"{key}\"] with number = {float_string}");
+        Ok(())
     }
 
     fn fetch(&self, key: &str) -> Result<BigDecimal, ServerError> {
-        if let Some(fetched) = self.tree.get(key.as_bytes())? {
-            let float_string = str::from_utf8(fetched.as_bytes())?;
-            let big_float = BigDecimal::from_str(float_string)?;
-            info!("fetch [\"{key}\"] value: {float_string}");
+        if let Some(fetched) = self.get(key)? {
+            let big_float = parse_value(&fetched)?;
+            info!("fetch [\"{key}\"] value: {big_float}");
             Ok(big_float)
         } else {
             Err(ServerError::DbKeyNotFound(key.into()))
         }
     }
 
-    fn update(&self, key: &str, new_value: BigDecimal) -> Result<(), ServerError> {
-        if self.tree.contains_key(key.as_bytes())? {
+    /// replace the value of existing key entry with `new_value`, and return the previous value.
+    fn update(&self, key: &str, new_value: BigDecimal) -> Result<BigDecimal, ServerError> {
+        if self.get(key)?.is_some() {
             let new_float_string = new_value.to_string();
             let old_val_bytes = self
-                .tree
-                .insert(key.as_bytes(), new_float_string.as_bytes())?
+                .insert(key, &new_float_string)?
                 .ok_or(ServerError::DbEmptyValue(key.into()))?;
-            let old_float_string = str::from_utf8(old_val_bytes.as_bytes())?;
-            info!("update [\"{key}\"] value from {old_float_string} to {new_float_string}");
-            Ok(())
+            let old_value = parse_value(&old_val_bytes)?;
+            info!("update [\"{key}\"] value from {old_value} to {new_float_string}");
+            Ok(old_value)
         } else {
             Err(ServerError::DbKeyUpdate(key.into()))
         }
     }
 
-    fn delete(&self, key: &str) -> Result<(), ServerError> {
-        if let Some(_deleted) = self.tree.remove(key.as_bytes())? {
+    /// write `value` into key entry regardless of its existence, and return the previous value
+    /// if it's replaced.
+    fn set(&self, key: &str, value: BigDecimal) -> Result<Option<BigDecimal>, ServerError> {
+        let float_string = value.to_string();
+        match self.insert(key, &float_string)? {
+            Some(old_val_bytes) => {
+                let previous = parse_value(&old_val_bytes)?;
+                info!("update [\"{key}\"] value from {previous} to {float_string}");
+                Ok(Some(previous))
+            }
+            None => {
+                info!("create new key entry [\"{key}\"] with number = {float_string}");
+                Ok(None)
+            }
+        }
+    }
+
+    /// replace the value of existing key entry with the result of `f` applied to current
+    /// value, and return the new value.
+    fn update_with<F>(&self, key: &str, f: F) -> Result<BigDecimal, ServerError>
+    where
+        F: Fn(&BigDecimal) -> Result<BigDecimal, ServerError>,
+    {
+        let current = self.fetch(key)?;
+        let new_value = f(&current)?;
+        let new_float_string = new_value.to_string();
+        self.insert(key, &new_float_string)?;
+        info!("update [\"{key}\"] value from {current} to {new_float_string}");
+        Ok(new_value)
+    }
+
+    /// add `delta` to the value of existing key entry rounded by `ctx`, and return the new value.
+    fn increment(
+        &self,
+        key: &str,
+        delta: &BigDecimal,
+        ctx: &MathContext,
+    ) -> Result<BigDecimal, ServerError> {
+        self.update_with(key, |current| BinaryOps::Add.apply(current, delta, ctx))
+    }
+
+    /// replace the value of existing key entry with `new_value` only if current value is
+    /// numerically equal to `expected`, e.g. `1.0` equals `1.00`.
+    fn compare_and_set(
+        &self,
+        key: &str,
+        expected: &BigDecimal,
+        new_value: BigDecimal,
+    ) -> Result<(), ServerError> {
+        let current = self.fetch(key)?;
+        if current != *expected {
+            return Err(ServerError::CasUnmatch {
+                key: key.into(),
+                current,
+            });
+        }
+        let new_float_string = new_value.to_string();
+        self.insert(key, &new_float_string)?;
+        info!("update [\"{key}\"] value from {current} to {new_float_string}");
+        Ok(())
+    }
+
+    /// remove the key entry, and return the deleted value.
+    fn delete(&self, key: &str) -> Result<BigDecimal, ServerError> {
+        if let Some(deleted) = self.remove(key)? {
             info!("[\"{key}\"] entry has been deleted from user database.");
-            Ok(())
+            parse_value(&deleted)
         } else {
             Err(ServerError::DbKeyNotFound(key.into()))
         }
     }
 }
+
+impl Storage for Tree {
+    fn get(&self, key: &str) -> Result<Option<IVec>, ServerError> {
+        Ok(Tree::get(self, key.as_bytes())?)
+    }
+
+    fn insert(&self, key: &str, value: &str) -> Result<Option<IVec>, ServerError> {
+        Ok(Tree::insert(self, key.as_bytes(), value.as_bytes())?)
+    }
+
+    fn remove(&self, key: &str) -> Result<Option<IVec>, ServerError> {
+        Ok(Tree::remove(self, key.as_bytes())?)
+    }
+
+    fn apply_batch(&self, batch: Batch) -> Result<(), ServerError> {
+        Ok(Tree::apply_batch(self, batch)?)
+    }
+
+    fn create(&self, key: &str, value: BigDecimal) -> Result<(), ServerError> {
+        let float_string = value.to_string();
+        match self.compare_and_swap(
+            key.as_bytes(),
+            None as Option<&[u8]>,
+            Some(float_string.as_bytes()),
+        )? {
+            Ok(_) => {
+                info!("create new key entry [\"{key}\"] with number = {float_string}");
+                Ok(())
+            }
+            Err(cas) => Err(cas.into()),
+        }
+    }
+
+    fn update_with<F>(&self, key: &str, f: F) -> Result<BigDecimal, ServerError>
+    where
+        F: Fn(&BigDecimal) -> Result<BigDecimal, ServerError>,
+    {
+        // retry until no other writer has replaced the value between fetching and swapping.
+        loop {
+            let current_bytes =
+                Tree::get(self, key.as_bytes())?.ok_or(ServerError::DbKeyNotFound(key.into()))?;
+            let current = parse_value(&current_bytes)?;
+            let new_value = f(&current)?;
+            let new_float_string = new_value.to_string();
+            if self
+                .compare_and_swap(
+                    key.as_bytes(),
+                    Some(&current_bytes),
+                    Some(new_float_string.as_bytes()),
+                )?
+                .is_ok()
+            {
+                info!("update [\"{key}\"] value from {current} to {new_float_string}");
+                return Ok(new_value);
+            }
+        }
+    }
+
+    fn increment(
+        &self,
+        key: &str,
+        delta: &BigDecimal,
+        ctx: &MathContext,
+    ) -> Result<BigDecimal, ServerError> {
+        let mut error = None;
+        let old_val_bytes = self.fetch_and_update(key.as_bytes(), |current| {
+            error = None;
+            let current = current?;
+            match parse_value(current).and_then(|value| BinaryOps::Add.apply(&value, delta, ctx)) {
+                Ok(new_value) => Some(new_value.to_string().into_bytes()),
+                Err(e) => {
+                    // leave the unparsable or out of range value untouched.
+                    error = Some(e);
+                    Some(current.to_vec())
+                }
+            }
+        })?;
+        if let Some(e) = error {
+            return Err(e);
+        }
+
+        let old_value = parse_value(&old_val_bytes.ok_or(ServerError::DbKeyNotFound(key.into()))?)?;
+        let new_value = BinaryOps::Add.apply(&old_value, delta, ctx)?;
+        info!("increment [\"{key}\"] value from {old_value} to {new_value}");
+        Ok(new_value)
+    }
+
+    fn compare_and_set(
+        &self,
+        key: &str,
+        expected: &BigDecimal,
+        new_value: BigDecimal,
+    ) -> Result<(), ServerError> {
+        let new_float_string = new_value.to_string();
+        // retry if the value is replaced by another writer with a numerically equal one.
+        loop {
+            let current_bytes =
+                Tree::get(self, key.as_bytes())?.ok_or(ServerError::DbKeyNotFound(key.into()))?;
+            let current = parse_value(&current_bytes)?;
+            if current != *expected {
+                return Err(ServerError::CasUnmatch {
+                    key: key.into(),
+                    current,
+                });
+            }
+            if self
+                .compare_and_swap(
+                    key.as_bytes(),
+                    Some(&current_bytes),
+                    Some(new_float_string.as_bytes()),
+                )?
+                .is_ok()
+            {
+                info!("update [\"{key}\"] value from {current} to {new_float_string}");
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl Storage for TransactionalTree {
+    fn get(&self, key: &str) -> Result<Option<IVec>, ServerError> {
+        Ok(TransactionalTree::get(self, key.as_bytes())?)
+    }
+
+    fn insert(&self, key: &str, value: &str) -> Result<Option<IVec>, ServerError> {
+        Ok(TransactionalTree::insert(
+            self,
+            key.as_bytes(),
+            value.as_bytes(),
+        )?)
+    }
+
+    fn remove(&self, key: &str) -> Result<Option<IVec>, ServerError> {
+        Ok(TransactionalTree::remove(self, key.as_bytes())?)
+    }
+
+    fn apply_batch(&self, batch: Batch) -> Result<(), ServerError> {
+        Ok(TransactionalTree::apply_batch(self, &batch)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::error::{ErrorCode, ErrorObject};
+
+    use std::thread;
+
+    fn temporary() -> UserDatabase {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let pool = ConnectionPool {
+            prefix: PathBuf::new(),
+            db,
+            definitions: Arc::default(),
+        };
+        pool.open_user_database(&b"test"[..]).unwrap()
+    }
+
+    fn call(database: &UserDatabase, method: &str, params: &[&str]) -> Result<Value, ServerError> {
+        let params = params
+            .iter()
+            .map(|param| Param::from(param.to_string()))
+            .collect();
+        let res = database.transaction(method.parse()?, params)?;
+        Ok(res.unwrap_or_else(|| Value::String("success".to_string())))
+    }
+
+    fn atomic(
+        database: &UserDatabase,
+        operations: &[(&str, &[&str])],
+    ) -> Result<Vec<Option<Value>>, ServerError> {
+        let operations = operations
+            .iter()
+            .map(|(method, params)| {
+                let params = params
+                    .iter()
+                    .map(|param| Param::from(param.to_string()))
+                    .collect();
+                (method.parse().unwrap(), params)
+            })
+            .collect();
+        database.atomic_transaction(operations)
+    }
+
+    fn read(database: &UserDatabase, key: &str) -> BigDecimal {
+        match call(database, "read", &[key]).unwrap() {
+            Value::String(value) => value.parse().unwrap(),
+            other => panic!("unexpected {other}"),
+        }
+    }
+
+    /// follow `cursor` of scanning `method` until it's `null`, return keys of every page.
+    fn pages(database: &UserDatabase, method: &str, params: &[&str]) -> Vec<Vec<String>> {
+        let mut pages = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let mut params = params.to_vec();
+            let option = cursor.map(|cursor| format!("cursor={cursor}"));
+            if let Some(option) = &option {
+                params.push(option);
+            }
+            let page = call(database, method, &params).unwrap();
+            let keys = page["entries"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|entry| entry[0].as_str().unwrap().to_string())
+                .collect();
+            pages.push(keys);
+            match &page["cursor"] {
+                Value::String(next) => cursor = Some(next.clone()),
+                Value::Null => return pages,
+                other => panic!("unexpected cursor {other}"),
+            }
+        }
+    }
+
+    #[test]
+    fn eval_number_expression() {
+        let database = temporary();
+        for (expression, expect) in [
+            ("42", "42"),
+            ("1e3", "1000"),
+            ("-0.5", "-0.5"),
+            ("1e100", "1e100"),
+        ] {
+            assert_eq!(
+                call(&database, "eval", &[expression]).unwrap(),
+                Value::String(BigDecimal::from_str(expect).unwrap().to_string()),
+                "{expression}"
+            );
+        }
+        call(&database, "eval", &["7", "a"]).unwrap();
+        assert_eq!(read(&database, "a"), BigDecimal::from(7));
+        assert!(matches!(
+            call(&database, "eval", &["1", "2"]),
+            Err(ServerError::MissingName(1))
+        ));
+    }
+
+    #[test]
+    fn concurrent_writes_are_not_lost() {
+        let database = Arc::new(temporary());
+        call(&database, "create", &["a", "0"]).unwrap();
+        call(&database, "create", &["b", "0"]).unwrap();
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let database = Arc::clone(&database);
+                thread::spawn(move || {
+                    for _ in 0..50 {
+                        call(&database, "increment", &["a"]).unwrap();
+                        call(&database, "add_assign", &["b", "2"]).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(read(&database, "a"), BigDecimal::from(200));
+        assert_eq!(read(&database, "b"), BigDecimal::from(400));
+    }
+
+    #[test]
+    fn increment_out_of_range() {
+        let database = temporary();
+        call(&database, "create", &["a", "1"]).unwrap();
+        for method in ["increment", "add_assign"] {
+            assert!(
+                matches!(
+                    call(&database, method, &["a", "1e-999999999"]),
+                    Err(ServerError::ArithmeticDomain(_))
+                ),
+                "{method}"
+            );
+        }
+        assert_eq!(read(&database, "a"), BigDecimal::from(1));
+    }
+
+    #[test]
+    fn compare_and_set_outside_transaction() {
+        let database = temporary();
+        call(&database, "create", &["a", "1"]).unwrap();
+        assert!(matches!(
+            call(&database, "create", &["a", "2"]),
+            Err(ServerError::SledCas(_))
+        ));
+        assert!(matches!(
+            call(&database, "cas", &["a", "2", "3"]),
+            Err(ServerError::CasUnmatch { current, .. }) if current == 1
+        ));
+        call(&database, "cas", &["a", "1.0", "3"]).unwrap();
+        assert_eq!(read(&database, "a"), BigDecimal::from(3));
+    }
+
+    #[test]
+    fn define_does_not_race_with_writes() {
+        let database = Arc::new(temporary());
+        call(&database, "create", &["a", "0"]).unwrap();
+        let writers: Vec<_> = (0..4)
+            .map(|_| {
+                let database = Arc::clone(&database);
+                thread::spawn(move || {
+                    for _ in 0..100 {
+                        call(&database, "increment", &["a"]).unwrap();
+                    }
+                })
+            })
+            .collect();
+        // every derived key entry defined between writes is recomputed by later writes.
+        for k in 1..=20 {
+            let expression = format!("a * {k}");
+            call(&database, "define", &[&format!("c{k}"), &expression]).unwrap();
+        }
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        assert_eq!(read(&database, "a"), BigDecimal::from(400));
+        for k in 1..=20 {
+            assert_eq!(read(&database, &format!("c{k}")), BigDecimal::from(400 * k));
+        }
+        assert!(matches!(
+            call(&database, "update", &["c1", "1"]),
+            Err(ServerError::DerivedKeyReadOnly(_))
+        ));
+    }
+
+    #[test]
+    fn atomic_batch_observes_preceding_writes() {
+        let database = temporary();
+        let results = atomic(
+            &database,
+            &[
+                ("create", &["a", "1"]),
+                ("add_assign", &["a", "2"]),
+                ("read", &["a"]),
+                ("define", &["c", "a * 10"]),
+                ("update", &["a", "4"]),
+                ("read", &["c"]),
+            ],
+        )
+        .unwrap();
+        assert_eq!(results.len(), 6);
+        assert_eq!(results[0], None);
+        assert_eq!(results[2], Some(Value::String("3".to_string())));
+        assert_eq!(results[5], Some(Value::String("40".to_string())));
+        assert_eq!(read(&database, "c"), BigDecimal::from(40));
+    }
+
+    #[test]
+    fn atomic_batch_rolls_back_on_failure() {
+        let database = temporary();
+        call(&database, "create", &["b", "5"]).unwrap();
+        let e = atomic(
+            &database,
+            &[
+                ("create", &["a", "1"]),
+                ("update", &["b", "6"]),
+                ("define", &["c", "a + b"]),
+                ("update", &["missing", "3"]),
+                ("create", &["d", "1"]),
+            ],
+        )
+        .unwrap_err();
+        assert!(matches!(
+            &e,
+            ServerError::AtomicBatch { index: 3, reason } if matches!(**reason, ServerError::DbKeyUpdate(_))
+        ));
+        assert_eq!(e.error_code(), ErrorCode::KeyNotFound);
+        let error = ErrorObject::from(e);
+        assert_eq!(error.code, -32002);
+        assert_eq!(error.data, Some(json!({ "index": 3 })));
+
+        // none of the operations before the failed one is applied.
+        assert!(matches!(
+            call(&database, "read", &["a"]),
+            Err(ServerError::DbKeyNotFound(_))
+        ));
+        assert_eq!(read(&database, "b"), BigDecimal::from(5));
+        assert!(database.formulas.is_empty());
+        assert!(database.dependents.is_empty());
+    }
+
+    #[test]
+    fn atomic_batch_rejects_scans() {
+        let database = temporary();
+        for (index, method) in ["list", "scan_prefix", "range", "sum", "count"]
+            .into_iter()
+            .enumerate()
+        {
+            let mut operations: Vec<(&str, &[&str])> = vec![("set", &["a", "1"]); index];
+            operations.push((method, &[]));
+            let e = atomic(&database, &operations).unwrap_err();
+            assert!(
+                matches!(
+                    &e,
+                    ServerError::AtomicBatch { index: i, reason }
+                        if *i == index && matches!(**reason, ServerError::NotAtomic(_))
+                ),
+                "{method}"
+            );
+            assert_eq!(e.error_code(), ErrorCode::InvalidParams);
+        }
+        assert!(database.tree.is_empty());
+    }
+
+    #[test]
+    fn follow_cursor_of_pages() {
+        let database = temporary();
+        for key in ["a1", "a2", "a3", "a4", "a5", "b1", "b2", "c1"] {
+            call(&database, "create", &[key, "1"]).unwrap();
+        }
+
+        for (method, params, expect) in [
+            (
+                "list",
+                &["limit=3"][..],
+                &[&["a1", "a2", "a3"][..], &["a4", "a5", "b1"], &["b2", "c1"]][..],
+            ),
+            // the last page is filled up exactly.
+            (
+                "list",
+                &["limit=4"],
+                &[&["a1", "a2", "a3", "a4"], &["a5", "b1", "b2", "c1"]],
+            ),
+            (
+                "list",
+                &[],
+                &[&["a1", "a2", "a3", "a4", "a5", "b1", "b2", "c1"]],
+            ),
+            (
+                "scan_prefix",
+                &["a", "limit=2"],
+                &[&["a1", "a2"], &["a3", "a4"], &["a5"]],
+            ),
+            ("scan_prefix", &["d", "limit=2"], &[&[]]),
+            (
+                "range",
+                &["a3", "b2", "limit=2"],
+                &[&["a3", "a4"], &["a5", "b1"]],
+            ),
+            (
+                "range",
+                &["a", "b", "limit=5"],
+                &[&["a1", "a2", "a3", "a4", "a5"]],
+            ),
+        ] {
+            assert_eq!(
+                pages(&database, method, params),
+                expect,
+                "{method} {params:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn cursor_out_of_bounds() {
+        let database = temporary();
+        for key in ["a1", "a2", "b1", "b2", "c1"] {
+            call(&database, "create", &[key, "1"]).unwrap();
+        }
+
+        for (method, params, expect) in [
+            ("list", &["cursor=b"][..], &["b1", "b2", "c1"][..]),
+            ("list", &["cursor=d"], &[]),
+            // a cursor before the prefix or `start` scans from the beginning.
+            ("scan_prefix", &["b", "cursor=a9"], &["b1", "b2"]),
+            ("scan_prefix", &["b", "cursor=b1"], &["b2"]),
+            ("scan_prefix", &["b", "cursor=c"], &[]),
+            ("range", &["a2", "c", "cursor=a"], &["a2", "b1", "b2"]),
+            ("range", &["a2", "c", "cursor=b1"], &["b2"]),
+            ("range", &["a2", "c", "cursor=c1"], &[]),
+            // `start >= end` is an empty range.
+            ("range", &["b1", "b1"], &[]),
+            ("range", &["c", "a"], &[]),
+            ("range", &["c", "a", "cursor=a"], &[]),
+        ] {
+            let entries: Vec<Value> = expect.iter().map(|key| json!([key, "1"])).collect();
+            assert_eq!(
+                call(&database, method, params).unwrap(),
+                json!({ "entries": entries, "cursor": null }),
+                "{method} {params:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_page_options() {
+        let database = temporary();
+        for (method, params) in [
+            ("list", &["limit=0"][..]),
+            ("list", &["limit=-1"]),
+            ("list", &["limit=ten"]),
+            ("scan_prefix", &["a", "limit=0"]),
+            ("range", &["a", "b", "limit=0"]),
+            ("list", &["precision=5"]),
+            ("range", &["a", "b", "rounding=up"]),
+        ] {
+            assert!(
+                matches!(
+                    call(&database, method, params),
+                    Err(ServerError::InvalidOption { .. })
+                ),
+                "{method} {params:?}"
+            );
+        }
+
+        // the page is capped at `MAX_PAGE_LIMIT` entries.
+        for idx in 0..=MAX_PAGE_LIMIT {
+            call(&database, "create", &[&format!("k{idx:04}"), "1"]).unwrap();
+        }
+        let page = call(&database, "list", &["limit=5000"]).unwrap();
+        assert_eq!(page["entries"].as_array().unwrap().len(), MAX_PAGE_LIMIT);
+        assert_eq!(page["cursor"], json!(format!("k{:04}", MAX_PAGE_LIMIT - 1)));
+    }
+
+    #[test]
+    fn aggregate_of_named_and_matched_keys() {
+        let database = temporary();
+        for (key, value) in [("a1", "1"), ("a2", "2"), ("ab", "3"), ("b1", "4")] {
+            call(&database, "create", &[key, value]).unwrap();
+        }
+
+        for (method, params, expect) in [
+            ("sum", &["a*"][..], "6"),
+            // key entries are counted once.
+            ("sum", &["a*", "a1", "ab*", "a*"], "6"),
+            ("count", &["a1", "b*", "a1"], "2"),
+            ("count", &["c*"], "0"),
+            ("maximum", &["*"], "4"),
+        ] {
+            assert_eq!(
+                call(&database, method, params).unwrap(),
+                json!(expect),
+                "{method} {params:?}"
+            );
+        }
+        assert!(matches!(
+            call(&database, "sum", &["a*", "c1"]),
+            Err(ServerError::DbKeyNotFound(key)) if &*key == "c1"
+        ));
+        assert!(matches!(
+            call(&database, "sum", &["precision=5"]),
+            Err(ServerError::MissingParam(1))
+        ));
+    }
+
+    #[test]
+    fn aggregate_observes_consistent_view() {
+        let database = Arc::new(temporary());
+        call(&database, "create", &["p0000", "1"]).unwrap();
+        let mover = {
+            let database = Arc::clone(&database);
+            thread::spawn(move || {
+                // move the only matched key entry forward in atomic batches.
+                for idx in 0..500 {
+                    let (from, to) = (format!("p{idx:04}"), format!("p{:04}", idx + 1));
+                    atomic(&database, &[("create", &[&to, "1"]), ("delete", &[&from])]).unwrap();
+                }
+            })
+        };
+        while !mover.is_finished() {
+            assert_eq!(call(&database, "count", &["p*"]).unwrap(), json!("1"));
+        }
+        mover.join().unwrap();
+        assert_eq!(
+            call(&database, "sum", &["p*", "p0500"]).unwrap(),
+            json!("1")
+        );
+    }
+}
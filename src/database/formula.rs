@@ -0,0 +1,396 @@
+use super::Storage;
+use crate::error::ServerError;
+use crate::expression::Expr;
+use crate::MathContext;
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::str::FromStr;
+
+use bigdecimal::BigDecimal;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use sled::transaction::TransactionalTree;
+
+/// The formula of derived key entry, which is recomputed whenever any of its dependencies is
+/// written.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) struct Formula {
+    /// the infix expression evaluated by [`Method::Eval`].
+    ///
+    /// [`Method::Eval`]: crate::Method::Eval
+    pub expression: String,
+    /// the key entries referred by `expression`.
+    pub dependencies: Vec<String>,
+    /// the reason of last failed recomputation, the derived key entry holds no value until
+    /// its dependencies are recovered.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The view of user database trees in a single `sled` transaction.
+pub(super) struct Formulas<'a> {
+    /// key entries of numbers.
+    pub values: &'a TransactionalTree,
+    /// formulas of derived key entries.
+    pub formulas: &'a TransactionalTree,
+    /// names of derived key entries depending on the key entry.
+    pub dependents: &'a TransactionalTree,
+}
+
+impl<'a> Formulas<'a> {
+    /// get the formula of derived key entry.
+    pub fn get(&self, key: &str) -> Result<Option<Formula>, ServerError> {
+        match self.formulas.get(key.as_bytes())? {
+            Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// define `key` as derived key entry of `expression` and return its value, fails if
+    /// `expression` refers to `key` itself directly or through other derived key entries.
+    pub fn define(
+        &self,
+        key: &str,
+        expression: &str,
+        ctx: &MathContext,
+    ) -> Result<BigDecimal, ServerError> {
+        let expr = Expr::from_str(expression)?;
+        let mut dependencies: Vec<String> = Vec::new();
+        for name in expr.identifiers() {
+            if !dependencies.iter().any(|dependency| dependency == name) {
+                dependencies.push(name.to_string());
+            }
+        }
+        self.detect_cycle(key, &dependencies)?;
+
+        let value = expr.evaluate(&|name: &str| self.values.fetch(name), ctx)?;
+        if let Some(formula) = self.get(key)? {
+            for dependency in &formula.dependencies {
+                self.unlink(dependency, key)?;
+            }
+        }
+        for dependency in &dependencies {
+            self.link(dependency, key)?;
+        }
+        self.put(
+            key,
+            &Formula {
+                expression: expression.to_string(),
+                dependencies,
+                error: None,
+            },
+        )?;
+        Storage::insert(self.values, key, &value.to_string())?;
+        info!("define [\"{key}\"] = {expression}, value: {value}");
+
+        Ok(value)
+    }
+
+    /// remove the formula of derived key entry, the value is left untouched.
+    pub fn undefine(&self, key: &str) -> Result<(), ServerError> {
+        if let Some(formula) = self.get(key)? {
+            for dependency in &formula.dependencies {
+                self.unlink(dependency, key)?;
+            }
+            self.formulas.remove(key.as_bytes())?;
+            info!("[\"{key}\"] is no longer a derived key entry.");
+        }
+        Ok(())
+    }
+
+    /// recompute every derived key entry depending on `key` directly or transitively, in order
+    /// of their dependencies.
+    pub fn propagate(&self, key: &str, ctx: &MathContext) -> Result<(), ServerError> {
+        // collect the derived key entries affected by `key`.
+        let mut affected: HashMap<String, Formula> = HashMap::new();
+        let mut queue: VecDeque<String> = self.dependents_of(key)?.into();
+        while let Some(name) = queue.pop_front() {
+            if affected.contains_key(&name) {
+                continue;
+            }
+            if let Some(formula) = self.get(&name)? {
+                queue.extend(self.dependents_of(&name)?);
+                affected.insert(name, formula);
+            }
+        }
+
+        // recompute a derived key entry after all of its affected dependencies.
+        let mut pending: HashMap<&str, usize> = affected
+            .iter()
+            .map(|(name, formula)| {
+                let count = formula
+                    .dependencies
+                    .iter()
+                    .filter(|dependency| affected.contains_key(dependency.as_str()))
+                    .count();
+                (name.as_str(), count)
+            })
+            .collect();
+        let mut ready: VecDeque<&str> = pending
+            .iter()
+            .filter(|(_, count)| **count == 0)
+            .map(|(name, _)| *name)
+            .collect();
+        while let Some(name) = ready.pop_front() {
+            self.recompute(name, &affected[name], ctx)?;
+            for (dependent, formula) in &affected {
+                if formula
+                    .dependencies
+                    .iter()
+                    .any(|dependency| dependency == name)
+                {
+                    let count = pending.get_mut(dependent.as_str()).unwrap();
+                    *count -= 1;
+                    if *count == 0 {
+                        ready.push_back(dependent);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// evaluate `formula` of derived key entry `key`, or put it into error state if evaluation
+    /// fails.
+    fn recompute(
+        &self,
+        key: &str,
+        formula: &Formula,
+        ctx: &MathContext,
+    ) -> Result<(), ServerError> {
+        let result = Expr::from_str(&formula.expression)
+            .and_then(|expr| expr.evaluate(&|name: &str| self.values.fetch(name), ctx));
+        let error = match result {
+            Ok(value) => {
+                Storage::insert(self.values, key, &value.to_string())?;
+                info!("recompute derived key [\"{key}\"] value: {value}");
+                None
+            }
+            // let `sled` retry the transaction on conflict.
+            Err(e @ ServerError::SledTransaction(_)) => return Err(e),
+            Err(e) => {
+                warn!("derived key [\"{key}\"] is unresolved, reason: {e}");
+                Storage::remove(self.values, key)?;
+                Some(e.to_string())
+            }
+        };
+        if formula.error != error {
+            let formula = Formula {
+                error,
+                ..formula.clone()
+            };
+            self.put(key, &formula)?;
+        }
+
+        Ok(())
+    }
+
+    /// return [`ServerError::FormulaCycle`] if `key` is reachable from `dependencies` through
+    /// formulas of derived key entries.
+    ///
+    /// [`ServerError::FormulaCycle`]: crate::error::ServerError::FormulaCycle
+    fn detect_cycle(&self, key: &str, dependencies: &[String]) -> Result<(), ServerError> {
+        let mut visited: HashSet<String> = HashSet::new();
+        let mut stack: Vec<String> = dependencies.to_vec();
+        while let Some(name) = stack.pop() {
+            if name == key {
+                return Err(ServerError::FormulaCycle(key.into()));
+            }
+            if !visited.insert(name.clone()) {
+                continue;
+            }
+            if let Some(formula) = self.get(&name)? {
+                stack.extend(formula.dependencies);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn put(&self, key: &str, formula: &Formula) -> Result<(), ServerError> {
+        let bytes = serde_json::to_vec(formula)?;
+        self.formulas.insert(key.as_bytes(), bytes)?;
+        Ok(())
+    }
+
+    fn dependents_of(&self, key: &str) -> Result<Vec<String>, ServerError> {
+        match self.dependents.get(key.as_bytes())? {
+            Some(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// record `dependent` as derived key entry depending on `key`.
+    fn link(&self, key: &str, dependent: &str) -> Result<(), ServerError> {
+        let mut dependents = self.dependents_of(key)?;
+        if !dependents.iter().any(|name| name == dependent) {
+            dependents.push(dependent.to_string());
+            self.dependents
+                .insert(key.as_bytes(), serde_json::to_vec(&dependents)?)?;
+        }
+        Ok(())
+    }
+
+    fn unlink(&self, key: &str, dependent: &str) -> Result<(), ServerError> {
+        let mut dependents = self.dependents_of(key)?;
+        dependents.retain(|name| name != dependent);
+        if dependents.is_empty() {
+            self.dependents.remove(key.as_bytes())?;
+        } else {
+            self.dependents
+                .insert(key.as_bytes(), serde_json::to_vec(&dependents)?)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use sled::transaction::{ConflictableTransactionError, TransactionError, Transactional};
+    use sled::Tree;
+
+    struct Trees {
+        values: Tree,
+        formulas: Tree,
+        dependents: Tree,
+    }
+
+    impl Trees {
+        fn temporary() -> Trees {
+            let db = sled::Config::new().temporary(true).open().unwrap();
+            Trees {
+                values: db.open_tree("values").unwrap(),
+                formulas: db.open_tree("formulas").unwrap(),
+                dependents: db.open_tree("dependents").unwrap(),
+            }
+        }
+
+        /// run `f` in a transaction, which is committed unless `f` fails.
+        fn run<T, F>(&self, f: F) -> Result<T, ServerError>
+        where
+            F: Fn(&Formulas) -> Result<T, ServerError>,
+        {
+            let trees = (&self.values, &self.formulas, &self.dependents);
+            trees
+                .transaction(|(values, formulas, dependents)| {
+                    let tx = Formulas {
+                        values,
+                        formulas,
+                        dependents,
+                    };
+                    f(&tx).map_err(ConflictableTransactionError::Abort)
+                })
+                .map_err(|e| match e {
+                    TransactionError::Abort(e) => e,
+                    TransactionError::Storage(e) => e.into(),
+                })
+        }
+
+        /// write `value` to `key` then recompute its dependents.
+        fn write(&self, key: &str, value: &str) {
+            self.run(|tx| {
+                Storage::insert(tx.values, key, value)?;
+                tx.propagate(key, &MathContext::default())
+            })
+            .unwrap();
+        }
+
+        fn define(&self, key: &str, expression: &str) -> Result<BigDecimal, ServerError> {
+            self.run(|tx| tx.define(key, expression, &MathContext::default()))
+        }
+
+        fn value(&self, key: &str) -> Option<BigDecimal> {
+            self.values.fetch(key).ok()
+        }
+    }
+
+    fn decimal(s: &str) -> BigDecimal {
+        BigDecimal::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn recompute_dependents_in_order() {
+        let trees = Trees::temporary();
+        trees.write("a", "1");
+        trees.write("b", "2");
+        assert_eq!(trees.define("c", "a + b").unwrap(), decimal("3"));
+        assert_eq!(trees.define("d", "c * a").unwrap(), decimal("3"));
+
+        // `d` depends on `a` directly and through `c`, it is recomputed after `c`.
+        trees.write("a", "10");
+        assert_eq!(trees.value("c"), Some(decimal("12")));
+        assert_eq!(trees.value("d"), Some(decimal("120")));
+
+        trees.write("b", "-10");
+        assert_eq!(trees.value("c"), Some(decimal("0")));
+        assert_eq!(trees.value("d"), Some(decimal("0")));
+    }
+
+    #[test]
+    fn error_state_and_recovery() {
+        let trees = Trees::temporary();
+        trees.write("a", "6");
+        trees.write("b", "3");
+        assert_eq!(trees.define("c", "a / b").unwrap(), decimal("2"));
+
+        trees
+            .run(|tx| {
+                Storage::remove(tx.values, "b")?;
+                tx.propagate("b", &MathContext::default())
+            })
+            .unwrap();
+        assert_eq!(trees.value("c"), None);
+        let formula = trees.run(|tx| tx.get("c")).unwrap().unwrap();
+        assert!(formula.error.is_some());
+
+        trees.write("b", "0");
+        assert_eq!(trees.value("c"), None);
+
+        trees.write("b", "2");
+        assert_eq!(trees.value("c"), Some(decimal("3")));
+        let formula = trees.run(|tx| tx.get("c")).unwrap().unwrap();
+        assert_eq!(formula.error, None);
+    }
+
+    #[test]
+    fn reject_cycles() {
+        let trees = Trees::temporary();
+        trees.write("a", "1");
+        assert!(matches!(
+            trees.define("a", "a + 1"),
+            Err(ServerError::FormulaCycle(_))
+        ));
+        trees.define("b", "a + 1").unwrap();
+        trees.define("c", "b * 2").unwrap();
+        assert!(matches!(
+            trees.define("a", "c - 1"),
+            Err(ServerError::FormulaCycle(_))
+        ));
+
+        // the rejected definition leaves `a` as it was.
+        assert!(trees.run(|tx| tx.get("a")).unwrap().is_none());
+        trees.write("a", "2");
+        assert_eq!(trees.value("c"), Some(decimal("6")));
+    }
+
+    #[test]
+    fn redefine_and_undefine() {
+        let trees = Trees::temporary();
+        trees.write("a", "1");
+        trees.write("b", "2");
+        trees.define("c", "a * 10").unwrap();
+        assert_eq!(trees.define("c", "b * 10").unwrap(), decimal("20"));
+
+        // `c` no longer depends on `a`.
+        trees.write("a", "5");
+        assert_eq!(trees.value("c"), Some(decimal("20")));
+
+        trees.run(|tx| tx.undefine("c")).unwrap();
+        trees.write("b", "3");
+        assert_eq!(trees.value("c"), Some(decimal("20")));
+        assert!(trees.dependents.is_empty());
+    }
+}
@@ -1,9 +1,8 @@
+use bigdecimal::BigDecimal;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use thiserror::Error;
 
-//TODO: organise errors into Json format with predefined error code.
-//e.g.
-//#[error("JSON RPC error_code: {code}, error_message: {msg}")]
-//JsonRpc { code: ErrorCode, msg: Box<str> },
 #[derive(Debug, Error)]
 pub enum ClientError {
     #[error("client didn't receive JSON response due to timeout.")]
@@ -12,6 +11,25 @@ pub enum ClientError {
     Json(#[from] serde_json::Error),
     #[error(transparent)]
     Io(#[from] std::io::Error),
+    #[error("unrecognisable datagram payload: {0}")]
+    Frame(#[from] FrameError),
+    #[error("JSON RPC error_code: {}, error_message: {}", .0.code, .0.message)]
+    Rpc(ErrorObject),
+    #[error("client stopped receiving JSON response.")]
+    Disconnected,
+    #[error("failed to parse JSON result into big decimal number: {0}")]
+    ParseNumber(#[from] bigdecimal::ParseBigDecimalError),
+}
+
+/// The error of decoding datagram payload composed by JSON bytes and crc32 checksum.
+#[derive(Debug, Error)]
+pub enum FrameError {
+    #[error("datagram payload is too short to contain checksum, length: {0}")]
+    TooShort(usize),
+    #[error("datagram checksum unmatched, expect: {expect}, actual: {actual}")]
+    ChecksumMismatch { expect: u32, actual: u32 },
+    #[error("invalid JSON in datagram payload: {0}")]
+    InvalidJson(#[from] serde_json::Error),
 }
 
 #[derive(Debug, Error)]
@@ -24,6 +42,8 @@ pub enum ServerError {
     ParseParamLiteral(#[from] std::str::Utf8Error),
     #[error("failed to parse param values into big decimal number.")]
     ParseParamNumeric(#[from] bigdecimal::ParseBigDecimalError),
+    #[error("method `{0}` is not found")]
+    MethodNotFound(Box<str>),
     #[error("missing {0} parameter")]
     MissingParam(usize),
     #[error("the parameter at index {0} must be a name.")]
@@ -42,56 +62,320 @@ pub enum ServerError {
     SledCas(#[from] sled::CompareAndSwapError),
     #[error("ACID transaction error from user database, reason: {0}")]
     SledInternal(#[from] sled::Error),
+    #[error("atomic batch transaction error from user database, reason: {0}")]
+    SledTransaction(#[from] sled::transaction::UnabortableTransactionError),
+    #[error("atomic batch aborted at operation index {index}, reason: {reason}")]
+    AtomicBatch {
+        index: usize,
+        reason: Box<ServerError>,
+    },
     #[error("value error, expect: {expect}, actual: {actual}")]
     ValueError { expect: Box<str>, actual: Box<str> },
+    #[error("server didn't complete the request due to timeout.")]
+    Timeout,
+    #[error("[\"{key}\"] value is not equal to expected value, current: {current}")]
+    CasUnmatch { key: Box<str>, current: BigDecimal },
+    #[error("division by zero.")]
+    DivisionByZero,
+    #[error("arithmetic domain error: {0}")]
+    ArithmeticDomain(Box<str>),
+    #[error("invalid option `{name}={value}`.")]
+    InvalidOption { name: Box<str>, value: Box<str> },
+    #[error("failed to parse expression, reason: {0}")]
+    ParseExpression(Box<str>),
+    #[error("formula of [\"{0}\"] refers to itself.")]
+    FormulaCycle(Box<str>),
+    #[error("derived key [\"{key}\"] is unresolved, reason: {reason}")]
+    DerivedKeyUnresolved { key: Box<str>, reason: Box<str> },
+    #[error("derived key [\"{0}\"] cannot be written.")]
+    DerivedKeyReadOnly(Box<str>),
+    #[error("method `{0}` is not supported in atomic batches.")]
+    NotAtomic(Box<str>),
+    #[error("the parameter at index {0} is an inexact JSON number, pass it as JSON string.")]
+    InexactNumber(usize),
 }
 
-/// The content of error message required by JSON "error" attribute in JSON-RPC response.
-pub struct ErrorMsg(String);
+/// The error codes of JSON "error" object in JSON-RPC response.
+///
+/// NOTE:
+///     - codes from -32768 to -32000 are reserved by [JSON-RPC 2.0 Specification], the
+///     standard codes are used for errors of JSON parsing and method invocation.
+///     - codes from -32000 to -32099 are reserved for implementation-defined server errors,
+///     which are used as application errors raised by `acrudjson` server and user database.
+///
+/// | code   | error              | [`ServerError`] variants                                 |
+/// |--------|--------------------|----------------------------------------------------------|
+/// | -32700 | parse error        | `ParseJson`                                              |
+/// | -32600 | invalid request    |                                                          |
+/// | -32601 | method not found   | `MethodNotFound`                                         |
+/// | -32602 | invalid params     | `ParseParamLiteral`, `ParseParamNumeric`, `MissingParam`, `MissingName`, `MissingNumber`, `ValueError`, `InvalidOption`, `ParseExpression`, `NotAtomic`, `InexactNumber` |
+/// | -32603 | internal error     | `Io`                                                     |
+/// | -32000 | timeout            | `Timeout`                                                |
+/// | -32001 | checksum unmatched | `ChecksumUnmatch`                                        |
+/// | -32002 | key not found      | `DbKeyNotFound`, `DbKeyUpdate`                           |
+/// | -32003 | key already exists | `SledCas`                                                |
+/// | -32004 | empty value        | `DbEmptyValue`                                           |
+/// | -32005 | storage error      | `SledInternal`, `SledTransaction`                        |
+/// | -32006 | value unmatched    | `CasUnmatch`, with current value in "data" member        |
+/// | -32007 | division by zero   | `DivisionByZero`                                         |
+/// | -32008 | arithmetic domain  | `ArithmeticDomain`                                       |
+/// | -32009 | formula cycle      | `FormulaCycle`                                           |
+/// | -32010 | derived key        | `DerivedKeyUnresolved`, `DerivedKeyReadOnly`             |
+///
+/// `ServerError::AtomicBatch` is reported with the code of the error aborting the batch, and
+/// the index of the failed operation in "data" member.
+///
+/// [JSON-RPC 2.0 Specification]: https://www.jsonrpc.org/specification#error_object
+/// [`ServerError`]: crate::error::ServerError
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ErrorCode {
+    ParseError,
+    InvalidRequest,
+    MethodNotFound,
+    InvalidParams,
+    InternalError,
+    Timeout,
+    ChecksumUnmatch,
+    KeyNotFound,
+    KeyExists,
+    EmptyValue,
+    Storage,
+    CasUnmatch,
+    DivisionByZero,
+    ArithmeticDomain,
+    FormulaCycle,
+    DerivedKey,
+}
 
-impl ErrorMsg {
-    /// create new `ErrorMsg`
-    pub fn new(msg: String) -> Self {
-        ErrorMsg(msg)
+impl ErrorCode {
+    /// get the number of error code in JSON "error" object.
+    pub fn code(&self) -> i64 {
+        match *self {
+            ErrorCode::ParseError => -32700,
+            ErrorCode::InvalidRequest => -32600,
+            ErrorCode::MethodNotFound => -32601,
+            ErrorCode::InvalidParams => -32602,
+            ErrorCode::InternalError => -32603,
+            ErrorCode::Timeout => -32000,
+            ErrorCode::ChecksumUnmatch => -32001,
+            ErrorCode::KeyNotFound => -32002,
+            ErrorCode::KeyExists => -32003,
+            ErrorCode::EmptyValue => -32004,
+            ErrorCode::Storage => -32005,
+            ErrorCode::CasUnmatch => -32006,
+            ErrorCode::DivisionByZero => -32007,
+            ErrorCode::ArithmeticDomain => -32008,
+            ErrorCode::FormulaCycle => -32009,
+            ErrorCode::DerivedKey => -32010,
+        }
     }
+}
 
-    /// consume `Self` and return inner value.
-    pub fn into_inner(self) -> String {
-        self.0
+impl TryFrom<i64> for ErrorCode {
+    type Error = i64;
+
+    fn try_from(value: i64) -> Result<Self, Self::Error> {
+        match value {
+            -32700 => Ok(ErrorCode::ParseError),
+            -32600 => Ok(ErrorCode::InvalidRequest),
+            -32601 => Ok(ErrorCode::MethodNotFound),
+            -32602 => Ok(ErrorCode::InvalidParams),
+            -32603 => Ok(ErrorCode::InternalError),
+            -32000 => Ok(ErrorCode::Timeout),
+            -32001 => Ok(ErrorCode::ChecksumUnmatch),
+            -32002 => Ok(ErrorCode::KeyNotFound),
+            -32003 => Ok(ErrorCode::KeyExists),
+            -32004 => Ok(ErrorCode::EmptyValue),
+            -32005 => Ok(ErrorCode::Storage),
+            -32006 => Ok(ErrorCode::CasUnmatch),
+            -32007 => Ok(ErrorCode::DivisionByZero),
+            -32008 => Ok(ErrorCode::ArithmeticDomain),
+            -32009 => Ok(ErrorCode::FormulaCycle),
+            -32010 => Ok(ErrorCode::DerivedKey),
+            unknown => Err(unknown),
+        }
     }
 }
 
-impl From<ServerError> for ErrorMsg {
+impl ServerError {
+    /// get the [`ErrorCode`] reported in JSON "error" object.
+    ///
+    /// [`ErrorCode`]: crate::error::ErrorCode
+    pub fn error_code(&self) -> ErrorCode {
+        match self {
+            ServerError::ChecksumUnmatch { .. } => ErrorCode::ChecksumUnmatch,
+            ServerError::ParseJson(_) => ErrorCode::ParseError,
+            ServerError::MethodNotFound(_) => ErrorCode::MethodNotFound,
+            ServerError::ParseParamLiteral(_)
+            | ServerError::ParseParamNumeric(_)
+            | ServerError::MissingParam(_)
+            | ServerError::MissingName(_)
+            | ServerError::MissingNumber(_)
+            | ServerError::ValueError { .. }
+            | ServerError::InvalidOption { .. }
+            | ServerError::ParseExpression(_)
+            | ServerError::NotAtomic(_)
+            | ServerError::InexactNumber(_) => ErrorCode::InvalidParams,
+            ServerError::DbKeyNotFound(_) | ServerError::DbKeyUpdate(_) => ErrorCode::KeyNotFound,
+            ServerError::DbEmptyValue(_) => ErrorCode::EmptyValue,
+            ServerError::SledCas(_) => ErrorCode::KeyExists,
+            ServerError::SledInternal(_) | ServerError::SledTransaction(_) => ErrorCode::Storage,
+            ServerError::AtomicBatch { reason, .. } => reason.error_code(),
+            ServerError::Timeout => ErrorCode::Timeout,
+            ServerError::CasUnmatch { .. } => ErrorCode::CasUnmatch,
+            ServerError::DivisionByZero => ErrorCode::DivisionByZero,
+            ServerError::ArithmeticDomain(_) => ErrorCode::ArithmeticDomain,
+            ServerError::FormulaCycle(_) => ErrorCode::FormulaCycle,
+            ServerError::DerivedKeyUnresolved { .. } | ServerError::DerivedKeyReadOnly(_) => {
+                ErrorCode::DerivedKey
+            }
+            ServerError::Io(_) => ErrorCode::InternalError,
+        }
+    }
+}
+
+/// The JSON "error" object required by JSON-RPC response when there's an error invoking the
+/// method.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ErrorObject {
+    /// a number that indicates the error type that occurred, see [`ErrorCode`].
+    ///
+    /// [`ErrorCode`]: crate::error::ErrorCode
+    pub code: i64,
+    /// a string providing a short description of the error.
+    pub message: String,
+    /// additional information about the error, this member MAY be omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl ErrorObject {
+    /// create new `ErrorObject` without "data" member.
+    pub fn new(code: ErrorCode, message: String) -> Self {
+        ErrorObject {
+            code: code.code(),
+            message,
+            data: None,
+        }
+    }
+
+    /// attach additional information as "data" member.
+    pub fn with_data(mut self, data: Value) -> Self {
+        self.data = Some(data);
+        self
+    }
+
+    /// get the [`ErrorCode`] of "code" member, return `None` if the code is not defined by
+    /// `acrudjson`.
+    ///
+    /// [`ErrorCode`]: crate::error::ErrorCode
+    pub fn error_code(&self) -> Option<ErrorCode> {
+        ErrorCode::try_from(self.code).ok()
+    }
+}
+
+impl From<ServerError> for ErrorObject {
     fn from(value: ServerError) -> Self {
+        let code = value.error_code();
         match value {
-            ServerError::ChecksumUnmatch { expect, actual } => ErrorMsg(format!(
-                "JSON RPC checksum unmatched, expect: {expect}, actual: {actual}"
-            )),
-            ServerError::ParseJson(_) => ErrorMsg("failed to parse JSON attributes.".to_string()),
-            ServerError::ParseParamLiteral(_) => {
-                ErrorMsg("failed to parse parameter into utf8-string.".to_string())
-            }
-            ServerError::ParseParamNumeric(_) => {
-                ErrorMsg("failed to parse paramater into floating number.".to_string())
-            }
-            ServerError::MissingParam(count) => ErrorMsg(format!("missing {count} parameter.")),
-            ServerError::MissingName(idx) => ErrorMsg(format!("index {idx} must be a name.")),
+            ServerError::ChecksumUnmatch { expect, actual } => ErrorObject::new(
+                code,
+                format!("JSON RPC checksum unmatched, expect: {expect}, actual: {actual}"),
+            ),
+            ServerError::ParseJson(_) => {
+                ErrorObject::new(code, "failed to parse JSON attributes.".to_string())
+            }
+            ServerError::ParseParamLiteral(_) => ErrorObject::new(
+                code,
+                "failed to parse parameter into utf8-string.".to_string(),
+            ),
+            ServerError::ParseParamNumeric(_) => ErrorObject::new(
+                code,
+                "failed to parse paramater into floating number.".to_string(),
+            ),
+            ServerError::MethodNotFound(method) => {
+                ErrorObject::new(code, format!("method `{method}` not found."))
+            }
+            ServerError::MissingParam(count) => {
+                ErrorObject::new(code, format!("missing {count} parameter."))
+            }
+            ServerError::MissingName(idx) => {
+                ErrorObject::new(code, format!("index {idx} must be a name."))
+            }
             ServerError::MissingNumber(idx) => {
-                ErrorMsg(format!("index {idx} must be decimal number."))
+                ErrorObject::new(code, format!("index {idx} must be decimal number."))
+            }
+            ServerError::DbKeyNotFound(key) => {
+                ErrorObject::new(code, format!("[\"{key}\"] not found."))
+            }
+            ServerError::DbEmptyValue(key) => {
+                ErrorObject::new(code, format!("[\"{key}\"] has empty value."))
+            }
+            ServerError::DbKeyUpdate(key) => {
+                ErrorObject::new(code, format!("[\"{key}\"] does not exist."))
+            }
+            ServerError::SledCas(_) => ErrorObject::new(
+                code,
+                "failed to create new value in user database".to_string(),
+            ),
+            ServerError::SledInternal(_) => ErrorObject::new(
+                code,
+                "failed to fetch or update value in user database.".to_string(),
+            ),
+            ServerError::SledTransaction(_) => ErrorObject::new(
+                code,
+                "failed to apply atomic batch in user database.".to_string(),
+            ),
+            ServerError::AtomicBatch { index, reason } => {
+                let reason: ErrorObject = (*reason).into();
+                ErrorObject::new(
+                    code,
+                    format!(
+                        "atomic batch aborted at operation index {index}: {}",
+                        reason.message
+                    ),
+                )
+                .with_data(serde_json::json!({ "index": index }))
+            }
+            ServerError::ValueError { .. } => ErrorObject::new(
+                code,
+                "failed to parse decimal number by requesting name.".to_string(),
+            ),
+            ServerError::Timeout => ErrorObject::new(code, "server timeout.".to_string()),
+            ServerError::CasUnmatch { key, current } => ErrorObject::new(
+                code,
+                format!("[\"{key}\"] value is not equal to expected value."),
+            )
+            .with_data(serde_json::json!({ "current": current.to_string() })),
+            ServerError::DivisionByZero => ErrorObject::new(code, "division by zero.".to_string()),
+            ServerError::ArithmeticDomain(reason) => {
+                ErrorObject::new(code, format!("arithmetic domain error: {reason}."))
+            }
+            ServerError::InvalidOption { name, value } => {
+                ErrorObject::new(code, format!("invalid option `{name}={value}`."))
+            }
+            ServerError::ParseExpression(reason) => {
+                ErrorObject::new(code, format!("invalid expression: {reason}."))
             }
-            ServerError::DbKeyNotFound(key) => ErrorMsg(format!("[\"{key}\"] not found.")),
-            ServerError::DbEmptyValue(key) => ErrorMsg(format!("[\"{key}\"] has empty value.")),
-            ServerError::DbKeyUpdate(key) => ErrorMsg(format!("[\"{key}\"] does not exist.")),
-            ServerError::SledCas(_) => {
-                ErrorMsg("failed to create new value in user database".to_string())
+            ServerError::FormulaCycle(key) => {
+                ErrorObject::new(code, format!("formula of [\"{key}\"] refers to itself."))
             }
-            ServerError::SledInternal(_) => {
-                ErrorMsg("failed to fetch or update value in user database.".to_string())
+            ServerError::DerivedKeyUnresolved { key, reason } => {
+                ErrorObject::new(code, format!("derived key [\"{key}\"] is unresolved."))
+                    .with_data(serde_json::json!({ "reason": reason }))
             }
-            ServerError::ValueError { .. } => {
-                ErrorMsg("failed to parse decimal number by requesting name.".to_string())
+            ServerError::DerivedKeyReadOnly(key) => {
+                ErrorObject::new(code, format!("derived key [\"{key}\"] cannot be written."))
             }
-            _ => ErrorMsg("internal I/O error.".to_string()),
+            ServerError::NotAtomic(method) => ErrorObject::new(
+                code,
+                format!("method `{method}` is not supported in atomic batches."),
+            ),
+            ServerError::InexactNumber(idx) => ErrorObject::new(
+                code,
+                format!("index {idx} must be decimal number in JSON string to keep precision."),
+            ),
+            ServerError::Io(_) => ErrorObject::new(code, "internal I/O error.".to_string()),
         }
     }
 }
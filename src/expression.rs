@@ -0,0 +1,459 @@
+use crate::error::ServerError;
+use crate::{BinaryOps, MathContext, Method, UnaryOps};
+
+use std::iter::Peekable;
+use std::str::{CharIndices, FromStr};
+
+use bigdecimal::BigDecimal;
+
+/// the maximum nesting depth of parentheses, unary operators and function calls.
+const MAX_DEPTH: usize = 64;
+/// the maximum number of tokens, which bounds the height of syntax tree evaluated recursively,
+/// e.g. `a + a + ...` is a left-deep tree as high as the number of operators.
+const MAX_TOKENS: usize = 512;
+
+/// The abstract syntax tree of infix arithmetic expression.
+///
+/// NOTE:
+///     - operators are `+`, `-`, `*`, `/`, `%` and right-associative `^` in ascending order of
+///     precedence, prefix `-` binds looser than `^`, e.g. `-2^2` is `-4`.
+///     - a function call is a [`UnaryOps`] applied to one argument, e.g. `sqrt(x)`, or a
+///     [`BinaryOps`] applied to two arguments, e.g. `max(x, 0)`.
+///     - identifiers are names of key entries in user database, which consist of alphanumeric
+///     characters, `_` and `.`.
+///
+/// [`UnaryOps`]: crate::UnaryOps
+/// [`BinaryOps`]: crate::BinaryOps
+#[derive(Debug, Clone)]
+pub(crate) enum Expr {
+    Number(BigDecimal),
+    Ident(Box<str>),
+    Unary(UnaryOps, Box<Expr>),
+    Binary(BinaryOps, Box<Expr>, Box<Expr>),
+}
+
+impl FromStr for Expr {
+    type Err = ServerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?.into_iter().peekable(),
+            depth: 0,
+        };
+        let expr = parser.expr()?;
+        match parser.tokens.next() {
+            None => Ok(expr),
+            Some(token) => Err(syntax_error(format!("unexpected {token}"))),
+        }
+    }
+}
+
+impl Expr {
+    /// evaluate the expression with values of identifiers from `resolve`, each intermediate
+    /// result is rounded by `ctx`.
+    pub(crate) fn evaluate<F>(
+        &self,
+        resolve: &F,
+        ctx: &MathContext,
+    ) -> Result<BigDecimal, ServerError>
+    where
+        F: Fn(&str) -> Result<BigDecimal, ServerError>,
+    {
+        match self {
+            Expr::Number(number) => Ok(ctx.round(number.clone())),
+            Expr::Ident(key) => resolve(key),
+            Expr::Unary(op, operand) => op.apply(&operand.evaluate(resolve, ctx)?, ctx),
+            Expr::Binary(op, lhs, rhs) => op.apply(
+                &lhs.evaluate(resolve, ctx)?,
+                &rhs.evaluate(resolve, ctx)?,
+                ctx,
+            ),
+        }
+    }
+
+    /// the names of identifiers in order of appearance.
+    pub(crate) fn identifiers(&self) -> Vec<&str> {
+        match self {
+            Expr::Number(_) => vec![],
+            Expr::Ident(key) => vec![key],
+            Expr::Unary(_, operand) => operand.identifiers(),
+            Expr::Binary(_, lhs, rhs) => {
+                let mut names = lhs.identifiers();
+                names.extend(rhs.identifiers());
+                names
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(BigDecimal),
+    Ident(Box<str>),
+    Op(char),
+    LeftParen,
+    RightParen,
+    Comma,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Number(number) => write!(f, "number `{number}`"),
+            Token::Ident(name) => write!(f, "identifier `{name}`"),
+            Token::Op(op) => write!(f, "operator `{op}`"),
+            Token::LeftParen => write!(f, "`(`"),
+            Token::RightParen => write!(f, "`)`"),
+            Token::Comma => write!(f, "`,`"),
+        }
+    }
+}
+
+fn syntax_error(reason: String) -> ServerError {
+    ServerError::ParseExpression(reason.into())
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, ServerError> {
+    let mut tokens = Vec::new();
+    let mut chars = s.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '0'..='9' | '.' => {
+                let end = scan_number(s, &mut chars);
+                let number = BigDecimal::from_str(&s[start..end])
+                    .map_err(|_| syntax_error(format!("invalid number `{}`", &s[start..end])))?;
+                tokens.push(Token::Number(number));
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut end = start;
+                while let Some(&(idx, c)) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_' || c == '.') {
+                        break;
+                    }
+                    end = idx + c.len_utf8();
+                    chars.next();
+                }
+                tokens.push(Token::Ident(s[start..end].into()));
+            }
+            '+' | '-' | '*' | '/' | '%' | '^' => {
+                tokens.push(Token::Op(c));
+                chars.next();
+            }
+            '(' => {
+                tokens.push(Token::LeftParen);
+                chars.next();
+            }
+            ')' => {
+                tokens.push(Token::RightParen);
+                chars.next();
+            }
+            ',' => {
+                tokens.push(Token::Comma);
+                chars.next();
+            }
+            other => return Err(syntax_error(format!("unexpected character `{other}`"))),
+        }
+    }
+
+    if tokens.len() > MAX_TOKENS {
+        return Err(syntax_error(format!(
+            "expression has more than {MAX_TOKENS} tokens"
+        )));
+    }
+
+    Ok(tokens)
+}
+
+/// consume digits, decimal point and exponent of number literal, return the end index.
+fn scan_number(s: &str, chars: &mut Peekable<CharIndices>) -> usize {
+    let mut end = s.len();
+    while let Some(&(idx, c)) = chars.peek() {
+        if !(c.is_ascii_digit() || c == '.') {
+            end = idx;
+            break;
+        }
+        chars.next();
+    }
+    // exponent is only consumed if it's followed by digits, e.g. `2e10` or `2E-3`.
+    let rest = &s[end..];
+    let mut exponent = rest.chars();
+    if let Some('e' | 'E') = exponent.next() {
+        let sign_len = match exponent.clone().next() {
+            Some('+' | '-') => 1,
+            _ => 0,
+        };
+        let digits = rest[1 + sign_len..]
+            .chars()
+            .take_while(char::is_ascii_digit)
+            .count();
+        if digits > 0 {
+            let exponent_end = end + 1 + sign_len + digits;
+            while let Some(&(idx, _)) = chars.peek() {
+                if idx >= exponent_end {
+                    break;
+                }
+                chars.next();
+            }
+            end = exponent_end;
+        }
+    }
+
+    end
+}
+
+/// Recursive descent parser of token stream.
+struct Parser<I: Iterator<Item = Token>> {
+    tokens: Peekable<I>,
+    depth: usize,
+}
+
+impl<I: Iterator<Item = Token>> Parser<I> {
+    /// expr := term (('+' | '-') term)*
+    fn expr(&mut self) -> Result<Expr, ServerError> {
+        let mut lhs = self.term()?;
+        while let Some(op) = self.next_op(&['+', '-']) {
+            let op = match op {
+                '+' => BinaryOps::Add,
+                _ => BinaryOps::Subtract,
+            };
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.term()?));
+        }
+
+        Ok(lhs)
+    }
+
+    /// term := factor (('*' | '/' | '%') factor)*
+    fn term(&mut self) -> Result<Expr, ServerError> {
+        let mut lhs = self.factor()?;
+        while let Some(op) = self.next_op(&['*', '/', '%']) {
+            let op = match op {
+                '*' => BinaryOps::Multiply,
+                '/' => BinaryOps::Divide,
+                _ => BinaryOps::Modulo,
+            };
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.factor()?));
+        }
+
+        Ok(lhs)
+    }
+
+    /// factor := ('-' | '+') factor | power
+    fn factor(&mut self) -> Result<Expr, ServerError> {
+        match self.next_op(&['-', '+']) {
+            Some(op) => {
+                self.enter()?;
+                let operand = self.factor()?;
+                self.depth -= 1;
+                Ok(match op {
+                    '-' => Expr::Unary(UnaryOps::Neg, Box::new(operand)),
+                    _ => operand,
+                })
+            }
+            None => self.power(),
+        }
+    }
+
+    /// power := primary ('^' factor)?
+    fn power(&mut self) -> Result<Expr, ServerError> {
+        let base = self.primary()?;
+        match self.next_op(&['^']) {
+            Some(_) => {
+                self.enter()?;
+                let exponent = self.factor()?;
+                self.depth -= 1;
+                Ok(Expr::Binary(
+                    BinaryOps::Pow,
+                    Box::new(base),
+                    Box::new(exponent),
+                ))
+            }
+            None => Ok(base),
+        }
+    }
+
+    /// primary := number | identifier | function '(' expr (',' expr)* ')' | '(' expr ')'
+    fn primary(&mut self) -> Result<Expr, ServerError> {
+        match self.tokens.next() {
+            Some(Token::Number(number)) => Ok(Expr::Number(number)),
+            Some(Token::Ident(name)) => {
+                if self.tokens.peek() != Some(&Token::LeftParen) {
+                    return Ok(Expr::Ident(name));
+                }
+                self.tokens.next();
+                self.enter()?;
+                let mut args = vec![self.expr()?];
+                while self.tokens.peek() == Some(&Token::Comma) {
+                    self.tokens.next();
+                    args.push(self.expr()?);
+                }
+                self.expect_right_paren()?;
+                self.depth -= 1;
+                call(&name, args)
+            }
+            Some(Token::LeftParen) => {
+                self.enter()?;
+                let expr = self.expr()?;
+                self.expect_right_paren()?;
+                self.depth -= 1;
+                Ok(expr)
+            }
+            Some(token) => Err(syntax_error(format!("unexpected {token}"))),
+            None => Err(syntax_error("unexpected end of expression".to_string())),
+        }
+    }
+
+    /// consume the next token if it's one of `ops`.
+    fn next_op(&mut self, ops: &[char]) -> Option<char> {
+        match self.tokens.peek() {
+            Some(Token::Op(op)) if ops.contains(op) => {
+                let op = *op;
+                self.tokens.next();
+                Some(op)
+            }
+            _ => None,
+        }
+    }
+
+    fn expect_right_paren(&mut self) -> Result<(), ServerError> {
+        match self.tokens.next() {
+            Some(Token::RightParen) => Ok(()),
+            Some(token) => Err(syntax_error(format!("expect `)`, found {token}"))),
+            None => Err(syntax_error("expect `)`".to_string())),
+        }
+    }
+
+    fn enter(&mut self) -> Result<(), ServerError> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(syntax_error(format!(
+                "expression is nested deeper than {MAX_DEPTH} levels"
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// compose function call of [`UnaryOps`] or [`BinaryOps`] by its method name.
+///
+/// [`UnaryOps`]: crate::UnaryOps
+/// [`BinaryOps`]: crate::BinaryOps
+fn call(name: &str, mut args: Vec<Expr>) -> Result<Expr, ServerError> {
+    match (name.parse::<Method>(), args.len()) {
+        (Ok(Method::Unary(op)), 1) => Ok(Expr::Unary(op, Box::new(args.remove(0)))),
+        (Ok(Method::Binary(op)), 2) => {
+            let rhs = args.remove(1);
+            Ok(Expr::Binary(op, Box::new(args.remove(0)), Box::new(rhs)))
+        }
+        (Ok(Method::Unary(_)), count) => Err(syntax_error(format!(
+            "function `{name}` takes 1 argument but {count} were supplied"
+        ))),
+        (Ok(Method::Binary(_)), count) => Err(syntax_error(format!(
+            "function `{name}` takes 2 arguments but {count} were supplied"
+        ))),
+        _ => Err(syntax_error(format!("unknown function `{name}`"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(expression: &str) -> Result<BigDecimal, ServerError> {
+        let resolve = |key: &str| match key {
+            "a" => Ok(BigDecimal::from(2)),
+            _ => Err(ServerError::DbKeyNotFound(key.into())),
+        };
+        Expr::from_str(expression)?.evaluate(&resolve, &MathContext::default())
+    }
+
+    fn assert_evaluates(expression: &str, expect: &str) {
+        let value = evaluate(expression).unwrap();
+        assert_eq!(value, BigDecimal::from_str(expect).unwrap(), "{expression}");
+    }
+
+    #[test]
+    fn precedence() {
+        assert_evaluates("1 + 2 * 3", "7");
+        assert_evaluates("(1 + 2) * 3", "9");
+        assert_evaluates("10 - 4 - 3", "3");
+        assert_evaluates("7 % 4 * 2", "6");
+        assert_evaluates("a * 3 - 1 / 4", "5.75");
+        assert_evaluates("max(a, 3) + min(-a, 0)", "1");
+        assert_evaluates("sqrt(16) ^ 2", "16");
+    }
+
+    #[test]
+    fn power_is_right_associative() {
+        assert_evaluates("2 ^ 3 ^ 2", "512");
+        assert_evaluates("(2 ^ 3) ^ 2", "64");
+        assert_evaluates("-2 ^ 2", "-4");
+        assert_evaluates("2 ^ -a", "0.25");
+    }
+
+    #[test]
+    fn identifiers() {
+        let expr = Expr::from_str("a + b.c * sqrt(a_1)").unwrap();
+        assert_eq!(expr.identifiers(), ["a", "b.c", "a_1"]);
+        assert!(matches!(
+            evaluate("a + b"),
+            Err(ServerError::DbKeyNotFound(key)) if &*key == "b"
+        ));
+    }
+
+    #[test]
+    fn syntax_errors() {
+        for expression in [
+            "",
+            "1 +",
+            "(1 + 2",
+            "1 + 2)",
+            "1 2",
+            "* 2",
+            "max(1)",
+            "sqrt(1, 2)",
+            "unknown(1)",
+            "1 # 2",
+            "1..2",
+        ] {
+            assert!(
+                matches!(
+                    Expr::from_str(expression),
+                    Err(ServerError::ParseExpression(_))
+                ),
+                "{expression}"
+            );
+        }
+        let nested = format!(
+            "{}1{}",
+            "(".repeat(MAX_DEPTH + 1),
+            ")".repeat(MAX_DEPTH + 1)
+        );
+        assert!(matches!(
+            Expr::from_str(&nested),
+            Err(ServerError::ParseExpression(_))
+        ));
+    }
+
+    #[test]
+    fn long_chain_fits_in_blocking_thread_stack() {
+        // 256 terms are 511 tokens, evaluated on a stack as small as `tokio` blocking thread.
+        let expression = vec!["a"; 256].join("+");
+        let handle = std::thread::Builder::new()
+            .stack_size(2 * 1024 * 1024)
+            .spawn(move || evaluate(&expression).map(|value| value.to_string()))
+            .unwrap();
+        assert_eq!(handle.join().unwrap().unwrap(), "512");
+    }
+
+    #[test]
+    fn too_many_tokens() {
+        let expression = vec!["a"; 20000].join("+");
+        assert!(matches!(
+            Expr::from_str(&expression),
+            Err(ServerError::ParseExpression(_))
+        ));
+    }
+}
@@ -0,0 +1,112 @@
+use crate::error::FrameError;
+
+use serde::{de::DeserializeOwned, Serialize};
+use zerocopy::{ByteSlice, LittleEndian, Ref, U32};
+
+type Checksum = U32<LittleEndian>;
+
+#[repr(C)]
+struct DatagramPayload<B> {
+    body: B,
+    checksum: Ref<B, Checksum>,
+}
+
+impl<B: ByteSlice> DatagramPayload<B> {
+    fn parse(bytes: B) -> Option<DatagramPayload<B>> {
+        let (body, checksum) = Ref::new_unaligned_from_suffix(bytes)?;
+        Some(DatagramPayload { body, checksum })
+    }
+
+    fn get_checksum(&self) -> u32 {
+        self.checksum.get()
+    }
+}
+
+/// serialize `body` into JSON then append the `u32` crc32 checksum of JSON bytes in
+/// little-endianness.
+pub fn encode<T: Serialize>(body: &T) -> Result<Vec<u8>, serde_json::Error> {
+    let payload = serde_json::to_vec(body)?;
+    Ok(seal(payload))
+}
+
+/// append the `u32` crc32 checksum of `body` in little-endianness.
+pub fn seal(mut body: Vec<u8>) -> Vec<u8> {
+    let checksum = crc32fast::hash(&body);
+    body.extend_from_slice(&checksum.to_le_bytes());
+
+    body
+}
+
+/// verify the crc32 checksum in tail bytes of `payload`, then return the JSON bytes in front of
+/// it.
+pub fn verify(payload: &[u8]) -> Result<&[u8], FrameError> {
+    let parsed = DatagramPayload::parse(payload).ok_or(FrameError::TooShort(payload.len()))?;
+    let actual = crc32fast::hash(parsed.body);
+    let expect = parsed.get_checksum();
+    if expect == actual {
+        Ok(parsed.body)
+    } else {
+        Err(FrameError::ChecksumMismatch { expect, actual })
+    }
+}
+
+/// verify the crc32 checksum in tail bytes of `payload`, then deserialize the JSON bytes into
+/// `T`.
+pub fn decode<T: DeserializeOwned>(payload: &[u8]) -> Result<T, FrameError> {
+    let body = verify(payload)?;
+    Ok(serde_json::from_slice(body)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::{json, Value};
+
+    #[test]
+    fn round_trip() {
+        let body = json!({"jsonrpc": "2.0", "method": "read", "params": ["a"], "id": 1});
+        let payload = encode(&body).unwrap();
+        assert_eq!(
+            &payload[..payload.len() - 4],
+            serde_json::to_vec(&body).unwrap()
+        );
+        assert_eq!(decode::<Value>(&payload).unwrap(), body);
+    }
+
+    #[test]
+    fn too_short() {
+        assert!(matches!(
+            decode::<Value>(&[1, 2, 3]),
+            Err(FrameError::TooShort(3))
+        ));
+        assert!(matches!(decode::<Value>(&[]), Err(FrameError::TooShort(0))));
+    }
+
+    #[test]
+    fn checksum_mismatch() {
+        let mut payload = encode(&json!([1, 2])).unwrap();
+        payload[1] = b'3';
+        let actual = crc32fast::hash(b"[3,2]");
+        match decode::<Value>(&payload) {
+            Err(FrameError::ChecksumMismatch {
+                expect,
+                actual: got,
+            }) => {
+                assert_ne!(expect, got);
+                assert_eq!(got, actual);
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    #[test]
+    fn invalid_json() {
+        let payload = seal(b"{\"id\":".to_vec());
+        assert_eq!(verify(&payload).unwrap(), b"{\"id\":");
+        assert!(matches!(
+            decode::<Value>(&payload),
+            Err(FrameError::InvalidJson(_))
+        ));
+    }
+}
@@ -1,4 +1,7 @@
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::ErrorObject;
 
 /// The JSON Request object following JSON-RPC 1.0 specification.
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -18,9 +21,9 @@ pub struct ReqBody {
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct RespBody {
     /// the member is required on `success`, MUST NOT exist on `error` invoking the method.
-    pub result: Option<String>,
+    pub result: Option<Value>,
     /// the member is required when there's an `error` invoking the method, MUST NOT exist on `success`.
-    pub error: Option<String>,
+    pub error: Option<ErrorObject>,
     /// an identifier corresponding to `id` member in same JSON Request object.
     pub id: usize,
 }
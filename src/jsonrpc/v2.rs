@@ -0,0 +1,99 @@
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+
+use crate::error::ErrorObject;
+
+/// An identifier established by the client following JSON-RPC 2.0 specification, which MUST
+/// contain a string, number or `null` value if included.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Id {
+    /// any JSON number, e.g. `18446744073709551615` beyond `i64`.
+    Number(serde_json::Number),
+    String(String),
+    Null,
+}
+
+/// The JSON Request object following JSON-RPC 2.0 specification.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ReqBody {
+    /// string of version of JSON-RPC protocol. MUST be exactly "2.0"
+    pub jsonrpc: String,
+    /// string containing the name of invoke method from public.
+    pub method: String,
+    /// an array of parameter values used by method during invocation, this member MAY be
+    /// omitted.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub params: Vec<Value>,
+    /// an identifier established by client, the request is a notification when the member is
+    /// omitted and the server MUST NOT reply to it.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_some"
+    )]
+    pub id: Option<Id>,
+}
+
+/// An operation of atomic batch carried by the "params" array of `rpc.atomic` request, every
+/// operation is applied in a single transaction on user database.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Operation {
+    /// string containing the name of invoke method from public.
+    pub method: String,
+    /// an array of parameter values used by method during invocation.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub params: Vec<Value>,
+}
+
+/// A JSON-RPC 2.0 call which contains either a single Request object or a batch array filled
+/// with Request objects.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Request {
+    Batch(Vec<ReqBody>),
+    Single(ReqBody),
+}
+
+/// The JSON Response object following JSON-RPC 2.0 specification.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct RespBody {
+    /// string of version of JSON-RPC protocol. MUST be exactly "2.0"
+    pub jsonrpc: String,
+    /// the member is required on `success`, MUST NOT exist on `error` invoking the method.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_some"
+    )]
+    pub result: Option<Value>,
+    /// the member is required when there's an `error` invoking the method, MUST NOT exist on `success`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorObject>,
+    /// an identifier corresponding to `id` member in same JSON Request object, it MUST be `null`
+    /// if the `id` cannot be detected from the request.
+    pub id: Id,
+}
+
+/// A JSON-RPC 2.0 reply which contains either a single Response object or a batch array filled
+/// with Response objects corresponding to a batch [`Request`].
+///
+/// [`Request`]: crate::prelude::v2::Request
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Response {
+    Batch(Vec<RespBody>),
+    Single(RespBody),
+}
+
+/// the reserved method name of atomic batch extension.
+pub const ATOMIC_METHOD: &str = "rpc.atomic";
+
+/// distinguish an explicit `null` from an omitted member.
+fn deserialize_some<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
@@ -2,15 +2,35 @@
 //!
 //! [JSON-RPC Specification]: https://www.jsonrpc.org/specification
 
+/// arithmetic of big decimal numbers invoked by [`Method`]s.
+///
+/// [`Method`]: crate::Method
+mod arithmetic;
+/// asynchronous UDP client invoking JSON-RPC methods on `acrudjson` server.
+#[cfg(feature = "tokio")]
+pub mod client;
 /// connection pool and data transaction queries for user database instances.
 pub mod database;
 /// server and client error types with error message constructor for JSON response payload.
 pub mod error;
+/// infix arithmetic expression evaluated by [`Method::Eval`].
+///
+/// [`Method::Eval`]: crate::Method::Eval
+mod expression;
+/// datagram payload codec composed by JSON bytes and crc32 checksum in tail bytes.
+pub mod frame;
 mod jsonrpc;
+/// asynchronous UDP server dispatching JSON-RPC requests to user database.
+#[cfg(feature = "tokio")]
+pub mod server;
 
 use std::fmt;
+use std::str::FromStr;
+
+pub use arithmetic::{MathContext, RoundingMode};
 
 use bigdecimal::BigDecimal;
+use error::ServerError;
 
 /// A JSON object to invoke basic CRUD implementation of `acrudjson` through
 /// JSON-RPC protocol. It can be used for [`RequestBuilder`] in frontend without
@@ -22,24 +42,140 @@ use bigdecimal::BigDecimal;
 ///
 /// [`RequestBuilder`]: crate::prelude::v1::RequestBuilder
 /// [JSON-RPC 2.0 Specification]: https://www.jsonrpc.org/specification
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Method {
     Create,
     Read,
     Update,
+    /// write the key entry regardless of its existence, and report whether it's inserted or
+    /// replaced with the previous value.
+    Set,
     Delete,
     Binary(BinaryOps),
+    /// compute the result of unary operation on the key entry.
+    Unary(UnaryOps),
+    /// compare the key entry with the other operand.
+    Compare(CompareOps),
+    /// test the key entry by predicate.
+    Predicate(PredicateOps),
+    /// store the result of binary operation into the key entry of left-hand side operand.
+    Assign(BinaryOps),
+    /// add an amount (default to `1`) to the key entry atomically.
+    Increment,
+    /// subtract an amount (default to `1`) from the key entry atomically.
+    Decrement,
+    /// update the key entry to a new value only if it's numerically equal to an expected value.
+    Cas,
+    /// evaluate an infix expression over key entries in a consistent snapshot, and store the
+    /// result into a target key entry if provided.
+    Eval,
+    /// define a derived key entry by an infix expression, which is recomputed whenever its
+    /// dependencies are written.
+    Define,
+    /// list key entries in ascending order of keys, paginated by `cursor` and `limit` options.
+    List,
+    /// list key entries beginning with a prefix, paginated by `cursor` and `limit` options.
+    ScanPrefix,
+    /// list key entries within a half-open range of keys, paginated by `cursor` and `limit`
+    /// options.
+    Range,
+    /// compute the aggregate of key entries listed by names or `"prefix*"` patterns.
+    Aggregate(AggregateOps),
+    /// read many key entries in a consistent snapshot, missing key entries are `null`.
+    MultiRead,
+    /// create many key entries from pairs of key and number atomically.
+    MultiCreate,
+    /// update many existing key entries from pairs of key and number atomically.
+    MultiUpdate,
 }
 
 /// Provide arithmetic of binary numbers wrapped by [`Method`].
 ///
 /// [`Method`]: crate::Method
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum BinaryOps {
     Add,
     Subtract,
     Multiply,
     Divide,
+    /// raise to the power of an integer exponent.
+    Pow,
+    /// remainder of truncated division, which has the same sign as left-hand side operand.
+    Modulo,
+    /// the lesser operand, or left-hand side operand if both are numerically equal.
+    Min,
+    /// the greater operand, or left-hand side operand if both are numerically equal.
+    Max,
+}
+
+/// Provide comparison of binary numbers wrapped by [`Method`].
+///
+/// [`Method`]: crate::Method
+#[derive(Debug, Clone)]
+pub enum CompareOps {
+    /// return `-1`, `0` or `1` if left-hand side operand is less than, equal to or greater than
+    /// right-hand side operand.
+    Compare,
+    Eq,
+    Lt,
+    Gt,
+}
+
+/// Provide boolean tests of a single number wrapped by [`Method`].
+///
+/// [`Method`]: crate::Method
+#[derive(Debug, Clone)]
+pub enum PredicateOps {
+    IsZero,
+    IsNegative,
+}
+
+/// Provide aggregates of many numbers wrapped by [`Method`].
+///
+/// NOTE: method names of `Min` and `Max` are `minimum` and `maximum`, which are distinguished
+/// from [`BinaryOps`].
+///
+/// [`Method`]: crate::Method
+/// [`BinaryOps`]: crate::BinaryOps
+#[derive(Debug, Clone)]
+pub enum AggregateOps {
+    Sum,
+    Product,
+    /// arithmetic mean.
+    Mean,
+    Min,
+    Max,
+    /// the number of key entries.
+    Count,
+    /// population variance.
+    Variance,
+}
+
+/// Provide arithmetic of a single number wrapped by [`Method`].
+///
+/// [`Method`]: crate::Method
+#[derive(Debug, Clone)]
+pub enum UnaryOps {
+    Abs,
+    Neg,
+    Sqrt,
+    /// round to integer by the rounding mode of [`MathContext`].
+    ///
+    /// [`MathContext`]: crate::MathContext
+    Round,
+    Floor,
+    Ceil,
+    /// exponential function `e^x`.
+    Exp,
+    /// natural logarithm.
+    Ln,
+    /// common logarithm with base 10.
+    Log,
+    /// sine of radians less than `10^101` in absolute value.
+    Sin,
+    /// cosine of radians less than `10^101` in absolute value.
+    Cos,
+    Atan,
 }
 
 /// A JSON object to provide key or value members for variable assignment or storing entries
@@ -48,23 +184,55 @@ pub enum BinaryOps {
 /// NOTE:
 ///     - `Param::Number(number)` is provided by [bigdecimal] for decimal representation and large
 ///     floating number computation.
+///     - string in form of `"name=value"` is parsed as `Param::Option` if `name` is one of
+///     [`OPTION_NAMES`], e.g. `"precision=20"` overrides the [`MathContext`] of invocation, and
+///     `"return_old=true"` returns the previous value as the result of `update` and `delete`
+///     methods, other strings containing `=` are `Param::Name`.
 ///
 /// [`UserDatabase`]: crate::database::UserDatabase
 /// [`Method`]: crate::Method
+/// [`MathContext`]: crate::MathContext
+/// [`OPTION_NAMES`]: crate::OPTION_NAMES
 /// [bigdecimal]: https://docs.rs/bigdecimal/latest/bigdecimal/
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Param {
     /// contains immutable utf8-string parsed from JSON string object
     Name(Box<str>),
     /// contains big decimal number parsed from JSON string object.
     Number(BigDecimal),
+    /// contains option name and value parsed from JSON string object in form of `"name=value"`.
+    Option { name: Box<str>, value: Box<str> },
+}
+
+/// the names of option params, which are reserved from key entries in form of `"name=value"`.
+pub const OPTION_NAMES: [&str; 5] = ["precision", "rounding", "cursor", "limit", "return_old"];
+
+impl From<String> for Param {
+    fn from(literal: String) -> Self {
+        if let Ok(number) = BigDecimal::from_str(&literal) {
+            return Param::Number(number);
+        }
+        match literal.split_once('=') {
+            Some((name, value)) if OPTION_NAMES.contains(&name) => Param::Option {
+                name: name.into(),
+                value: value.into(),
+            },
+            _ => Param::Name(literal.into_boxed_str()),
+        }
+    }
 }
 
 pub trait JsonInternal {
-    /// parse JSON member "method" value into `Method`.
-    fn parse_method(&self) -> Method;
-    /// parse JSON member "params" array values into `Vec<Param>`.
-    fn parse_params(&self) -> Vec<Param>;
+    /// parse JSON member "method" value into `Method`, return [`ServerError::MethodNotFound`]
+    /// if the method name is unrecognised.
+    ///
+    /// [`ServerError::MethodNotFound`]: crate::error::ServerError::MethodNotFound
+    fn parse_method(&self) -> Result<Method, ServerError>;
+    /// parse JSON member "params" array values into `Vec<Param>`, return
+    /// [`ServerError::InexactNumber`] if a value cannot be parsed without losing precision.
+    ///
+    /// [`ServerError::InexactNumber`]: crate::error::ServerError::InexactNumber
+    fn parse_params(&self) -> Result<Vec<Param>, ServerError>;
 }
 
 /// import `struct` implementations following JSON-RPC specification.
@@ -76,9 +244,7 @@ pub mod prelude {
         pub use crate::jsonrpc::v1::*;
         pub use crate::{JsonInternal, Method, Param};
 
-        use std::str::FromStr;
-
-        use bigdecimal::BigDecimal;
+        use serde_json::Value;
 
         /// Used to compose JSON request based on JSON-RPC 1.0 specification.
         ///
@@ -114,11 +280,7 @@ pub mod prelude {
 
             /// calculate crc32 checksum then append the bytes after request body.
             pub fn build(self) -> Result<Vec<u8>, serde_json::Error> {
-                let mut payload = serde_json::to_vec(&self.body)?;
-                let checksum = crc32fast::hash(&payload);
-                payload.extend_from_slice(&checksum.to_le_bytes());
-
-                Ok(payload)
+                crate::frame::encode(&self.body)
             }
         }
 
@@ -143,7 +305,7 @@ pub mod prelude {
             /// creates and return [`RespBody`] as builder.
             ///
             /// [`RespBody`]: crate::prelude::v1::RespBody
-            pub fn new(result: String, id: usize) -> Self {
+            pub fn new(result: Value, id: usize) -> Self {
                 ResponseBuilder {
                     body: RespBody {
                         result: Some(result),
@@ -158,21 +320,21 @@ pub mod prelude {
             pub fn success(id: usize) -> Self {
                 ResponseBuilder {
                     body: RespBody {
-                        result: Some("success".to_string()),
+                        result: Some(Value::String("success".to_string())),
                         error: None,
                         id,
                     },
                 }
             }
 
-            /// compose JSON response when target request proceeds failed with `ErrorMsg`
-            /// indicates error message in JSON "error" field.
+            /// compose JSON response when target request proceeds failed with `ErrorObject`
+            /// indicates error code and message in JSON "error" field.
             /// NOTE: the `id` should be same as target JSON request.
-            pub fn error(msg: ErrorMsg, id: usize) -> Self {
+            pub fn error(error: ErrorObject, id: usize) -> Self {
                 ResponseBuilder {
                     body: RespBody {
                         result: None,
-                        error: Some(msg.into_inner()),
+                        error: Some(error),
                         id,
                     },
                 }
@@ -180,37 +342,409 @@ pub mod prelude {
 
             /// calculate crc32 checksum then append the bytes after response body.
             pub fn build(self) -> Vec<u8> {
-                if let Ok(mut payload) = serde_json::to_vec(&self.body) {
-                    let checksum = crc32fast::hash(&payload);
-                    payload.extend_from_slice(&checksum.to_le_bytes());
-
-                    payload
-                } else {
-                    todo!()
-                }
+                // serializing the response never fails, its members are strings, integers and
+                // `Value` whose object keys are strings.
+                crate::frame::encode(&self.body).expect("JSON response is serializable")
             }
         }
 
         impl JsonInternal for ReqBody {
-            fn parse_method(&self) -> Method {
-                self.method.clone().into()
+            fn parse_method(&self) -> Result<Method, ServerError> {
+                self.method.parse()
+            }
+
+            fn parse_params(&self) -> Result<Vec<Param>, ServerError> {
+                let results: Vec<Param> =
+                    self.params.clone().into_iter().map(Param::from).collect();
+
+                Ok(results)
+            }
+        }
+    }
+
+    /// JSON-RPC 2.0 specification
+    pub mod v2 {
+        pub use crate::database::*;
+        pub use crate::error::*;
+        pub use crate::jsonrpc::v2::*;
+        pub use crate::{JsonInternal, Method, Param};
+
+        use serde_json::Value;
+
+        /// Used to compose JSON request based on JSON-RPC 2.0 specification.
+        ///
+        /// NOTE:
+        ///     - the result payload contains a `u32` crc32 checksum in little-endianness in tail
+        ///     bytes.
+        pub struct RequestBuilder {
+            body: Request,
+        }
+
+        impl RequestBuilder {
+            /// parse JSON string into a single or batch [`Request`] to initialise the builder.
+            ///
+            /// [`Request`]: crate::prelude::v2::Request
+            pub fn from_json(json_string: &str) -> Result<Self, ClientError> {
+                let body: Request = serde_json::from_str(json_string)?;
+                Ok(RequestBuilder { body })
             }
 
-            fn parse_params(&self) -> Vec<Param> {
-                let results: Vec<Param> = self
-                    .params
-                    .clone()
+            /// creates and return [`ReqBody`] as builder.
+            ///
+            /// NOTE: params are sent as JSON strings to preserve the precision of decimal
+            /// numbers.
+            ///
+            /// [`ReqBody`]: crate::prelude::v2::ReqBody
+            pub fn new(method: Method, params: Vec<String>, id: Id) -> Self {
+                RequestBuilder {
+                    body: Request::Single(ReqBody {
+                        jsonrpc: "2.0".to_string(),
+                        method: method.into(),
+                        params: params.into_iter().map(Value::String).collect(),
+                        id: Some(id),
+                    }),
+                }
+            }
+
+            /// creates and return [`ReqBody`] without `id` member as builder, the server will
+            /// not reply to the notification.
+            ///
+            /// [`ReqBody`]: crate::prelude::v2::ReqBody
+            pub fn notification(method: Method, params: Vec<String>) -> Self {
+                RequestBuilder {
+                    body: Request::Single(ReqBody {
+                        jsonrpc: "2.0".to_string(),
+                        method: method.into(),
+                        params: params.into_iter().map(Value::String).collect(),
+                        id: None,
+                    }),
+                }
+            }
+
+            /// creates `rpc.atomic` request as builder, every operation composed by [`Method`]
+            /// and params is applied all-or-nothing in a single transaction by the server.
+            ///
+            /// [`Method`]: crate::Method
+            pub fn atomic(operations: Vec<(Method, Vec<String>)>, id: Id) -> Self {
+                let params = operations
                     .into_iter()
-                    .map(|param| {
-                        if let Ok(number) = BigDecimal::from_str(&param) {
-                            Param::Number(number)
-                        } else {
-                            Param::Name(param.into_boxed_str())
-                        }
+                    .map(|(method, params)| {
+                        serde_json::json!(Operation {
+                            method: method.into(),
+                            params: params.into_iter().map(Value::String).collect(),
+                        })
                     })
                     .collect();
 
-                results
+                RequestBuilder {
+                    body: Request::Single(ReqBody {
+                        jsonrpc: "2.0".to_string(),
+                        method: ATOMIC_METHOD.to_string(),
+                        params,
+                        id: Some(id),
+                    }),
+                }
+            }
+
+            /// merge requests and notifications into a batch array which is sent in a single
+            /// datagram payload.
+            pub fn batch(requests: Vec<RequestBuilder>) -> Self {
+                let mut bodies = Vec::with_capacity(requests.len());
+                for request in requests {
+                    match request.body {
+                        Request::Single(body) => bodies.push(body),
+                        Request::Batch(batch) => bodies.extend(batch),
+                    }
+                }
+
+                RequestBuilder {
+                    body: Request::Batch(bodies),
+                }
+            }
+
+            /// calculate crc32 checksum then append the bytes after request body.
+            pub fn build(self) -> Result<Vec<u8>, serde_json::Error> {
+                crate::frame::encode(&self.body)
+            }
+        }
+
+        /// Used to compose JSON response based on JSON-RPC 2.0 specification.
+        ///
+        /// NOTE:
+        ///     - the result payload contains a `u32` crc32 checksum in little-endianness in tail
+        ///     bytes.
+        pub struct ResponseBuilder {
+            body: Response,
+        }
+
+        impl ResponseBuilder {
+            /// parse JSON string into a single or batch [`Response`] to initialise the builder.
+            ///
+            /// [`Response`]: crate::prelude::v2::Response
+            pub fn from_json(json_string: &str) -> Result<Self, ClientError> {
+                let body: Response = serde_json::from_str(json_string)?;
+                Ok(ResponseBuilder { body })
+            }
+
+            /// creates and return [`RespBody`] as builder.
+            ///
+            /// [`RespBody`]: crate::prelude::v2::RespBody
+            pub fn new(result: Value, id: Id) -> Self {
+                ResponseBuilder {
+                    body: Response::Single(RespBody {
+                        jsonrpc: "2.0".to_string(),
+                        result: Some(result),
+                        error: None,
+                        id,
+                    }),
+                }
+            }
+
+            /// compose JSON response when target request proceeds successfully.
+            /// NOTE: the `id` should be same as target JSON request.
+            pub fn success(id: Id) -> Self {
+                Self::new(Value::String("success".to_string()), id)
+            }
+
+            /// compose JSON response when target request proceeds failed with `ErrorObject`
+            /// indicates error code and message in JSON "error" object.
+            /// NOTE: the `id` should be same as target JSON request, or `Id::Null` if it cannot
+            /// be detected.
+            pub fn error(error: ErrorObject, id: Id) -> Self {
+                ResponseBuilder {
+                    body: Response::Single(RespBody {
+                        jsonrpc: "2.0".to_string(),
+                        result: None,
+                        error: Some(error),
+                        id,
+                    }),
+                }
+            }
+
+            /// merge responses into a batch array replying to a batch request.
+            pub fn batch(responses: Vec<ResponseBuilder>) -> Self {
+                let mut bodies = Vec::with_capacity(responses.len());
+                for response in responses {
+                    match response.body {
+                        Response::Single(body) => bodies.push(body),
+                        Response::Batch(batch) => bodies.extend(batch),
+                    }
+                }
+
+                ResponseBuilder {
+                    body: Response::Batch(bodies),
+                }
+            }
+
+            /// calculate crc32 checksum then append the bytes after response body.
+            pub fn build(self) -> Vec<u8> {
+                // serializing the response never fails, its members are strings, integers and
+                // `Value` whose object keys are strings.
+                crate::frame::encode(&self.body).expect("JSON response is serializable")
+            }
+        }
+
+        /// execute JSON-RPC 2.0 call from `json` request body against `database` through
+        /// [`UserDatabase::transaction`], and return the response to reply.
+        ///
+        /// NOTE:
+        ///     - each request object in a batch array is executed in order, the responses of
+        ///     notifications are omitted from the reply batch array.
+        ///     - `None` is returned when there is nothing to reply, i.e. the call is a single
+        ///     notification or a batch array only contains notifications.
+        ///     - an empty batch array is replied with a single "invalid request" response.
+        ///     - a request with by-name params object is replied with "invalid params" response,
+        ///     only by-position params array is supported.
+        ///
+        /// [`UserDatabase::transaction`]: crate::database::UserDatabase::transaction
+        pub fn dispatch(database: &UserDatabase, json: &[u8]) -> Option<ResponseBuilder> {
+            let call: Value = match serde_json::from_slice(json) {
+                Ok(call) => call,
+                Err(e) => {
+                    let error =
+                        ErrorObject::new(ErrorCode::ParseError, format!("parse error: {e}"));
+                    return Some(ResponseBuilder::error(error, Id::Null));
+                }
+            };
+
+            match call {
+                Value::Array(batch) if batch.is_empty() => {
+                    let error = ErrorObject::new(
+                        ErrorCode::InvalidRequest,
+                        "invalid request: empty batch array.".to_string(),
+                    );
+                    Some(ResponseBuilder::error(error, Id::Null))
+                }
+                Value::Array(batch) => {
+                    let responses: Vec<ResponseBuilder> = batch
+                        .into_iter()
+                        .filter_map(|request| execute(database, request))
+                        .collect();
+
+                    if responses.is_empty() {
+                        None
+                    } else {
+                        Some(ResponseBuilder::batch(responses))
+                    }
+                }
+                request => execute(database, request),
+            }
+        }
+
+        /// execute a single JSON-RPC 2.0 request object, return `None` for notification.
+        fn execute(database: &UserDatabase, request: Value) -> Option<ResponseBuilder> {
+            let req_body: ReqBody = match serde_json::from_value::<ReqBody>(request.clone()) {
+                Ok(req_body) if req_body.jsonrpc == "2.0" => req_body,
+                _ if has_by_name_params(&request) => {
+                    let id = serde_json::from_value::<Id>(request.get("id")?.clone()).ok()?;
+                    let error = ErrorObject::new(
+                        ErrorCode::InvalidParams,
+                        "invalid params: by-name params object is not supported, use an array."
+                            .to_string(),
+                    );
+                    return Some(ResponseBuilder::error(error, id));
+                }
+                _ => {
+                    // reply with the `id` member if it can be detected from the request.
+                    let id = request
+                        .get("id")
+                        .and_then(|id| serde_json::from_value(id.clone()).ok())
+                        .unwrap_or(Id::Null);
+                    let error =
+                        ErrorObject::new(ErrorCode::InvalidRequest, "invalid request.".to_string());
+                    return Some(ResponseBuilder::error(error, id));
+                }
+            };
+
+            if req_body.method == ATOMIC_METHOD {
+                return execute_atomic(database, req_body);
+            }
+
+            let result = req_body
+                .parse_method()
+                .and_then(|method| Ok((method, req_body.parse_params()?)))
+                .and_then(|(method, params)| database.transaction(method, params));
+            let id = req_body.id?;
+
+            let response = match result {
+                Ok(Some(res)) => ResponseBuilder::new(res, id),
+                Ok(None) => ResponseBuilder::success(id),
+                Err(e) => ResponseBuilder::error(e.into(), id),
+            };
+
+            Some(response)
+        }
+
+        /// execute `rpc.atomic` request whose params are [`Operation`]s in a single transaction,
+        /// the "result" array contains the result of each operation in order.
+        ///
+        /// [`Operation`]: crate::prelude::v2::Operation
+        fn execute_atomic(database: &UserDatabase, req_body: ReqBody) -> Option<ResponseBuilder> {
+            let operations: Result<Vec<Operation>, _> = req_body
+                .params
+                .into_iter()
+                .map(serde_json::from_value)
+                .collect();
+            let result = match operations {
+                Ok(operations) => operations
+                    .iter()
+                    .enumerate()
+                    .map(|(index, operation)| {
+                        operation
+                            .parse_method()
+                            .and_then(|method| Ok((method, operation.parse_params()?)))
+                            .map_err(|e| ServerError::AtomicBatch {
+                                index,
+                                reason: Box::new(e),
+                            })
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+                    .and_then(|operations| database.atomic_transaction(operations)),
+                Err(e) => {
+                    let id = req_body.id?;
+                    let error = ErrorObject::new(
+                        ErrorCode::InvalidParams,
+                        format!("invalid atomic batch operation: {e}"),
+                    );
+                    return Some(ResponseBuilder::error(error, id));
+                }
+            };
+            let id = req_body.id?;
+
+            let response = match result {
+                Ok(results) => {
+                    let results = results
+                        .into_iter()
+                        .map(|res| res.unwrap_or_else(|| Value::String("success".to_string())))
+                        .collect();
+                    ResponseBuilder::new(Value::Array(results), id)
+                }
+                Err(e) => ResponseBuilder::error(e.into(), id),
+            };
+
+            Some(response)
+        }
+
+        /// whether `request` is a valid request object except that its "params" member is an
+        /// object of by-name params.
+        fn has_by_name_params(request: &Value) -> bool {
+            let mut positional = match request {
+                Value::Object(members)
+                    if matches!(members.get("params"), Some(Value::Object(_))) =>
+                {
+                    members.clone()
+                }
+                _ => return false,
+            };
+            positional.remove("params");
+            matches!(
+                serde_json::from_value::<ReqBody>(Value::Object(positional)),
+                Ok(req_body) if req_body.jsonrpc == "2.0"
+            )
+        }
+
+        /// parse JSON "params" array values into `Vec<Param>`, non-string values are parsed from
+        /// their JSON text.
+        ///
+        /// NOTE: JSON numbers with fractional part or exponent are parsed into `f64` by
+        /// `serde_json`, so they are rejected by [`ServerError::InexactNumber`] instead of being
+        /// rounded silently, decimal numbers MUST be JSON strings, e.g. `"0.1"`.
+        ///
+        /// [`ServerError::InexactNumber`]: crate::error::ServerError::InexactNumber
+        fn parse_values(params: &[Value]) -> Result<Vec<Param>, ServerError> {
+            params
+                .iter()
+                .enumerate()
+                .map(|(idx, param)| {
+                    let literal = match param {
+                        Value::String(literal) => literal.clone(),
+                        Value::Number(number) if number.is_f64() => {
+                            return Err(ServerError::InexactNumber(idx))
+                        }
+                        other => other.to_string(),
+                    };
+                    Ok(Param::from(literal))
+                })
+                .collect()
+        }
+
+        impl JsonInternal for ReqBody {
+            fn parse_method(&self) -> Result<Method, ServerError> {
+                self.method.parse()
+            }
+
+            fn parse_params(&self) -> Result<Vec<Param>, ServerError> {
+                parse_values(&self.params)
+            }
+        }
+
+        impl JsonInternal for Operation {
+            fn parse_method(&self) -> Result<Method, ServerError> {
+                self.method.parse()
+            }
+
+            fn parse_params(&self) -> Result<Vec<Param>, ServerError> {
+                parse_values(&self.params)
             }
         }
     }
@@ -222,30 +756,140 @@ impl From<Method> for String {
             Method::Create => "create",
             Method::Read => "read",
             Method::Update => "update",
+            Method::Set => "set",
             Method::Delete => "delete",
             Method::Binary(BinaryOps::Add) => "add",
             Method::Binary(BinaryOps::Subtract) => "subtract",
             Method::Binary(BinaryOps::Multiply) => "multiply",
             Method::Binary(BinaryOps::Divide) => "divide",
+            Method::Binary(BinaryOps::Pow) => "pow",
+            Method::Binary(BinaryOps::Modulo) => "modulo",
+            Method::Binary(BinaryOps::Min) => "min",
+            Method::Binary(BinaryOps::Max) => "max",
+            Method::Unary(UnaryOps::Abs) => "abs",
+            Method::Unary(UnaryOps::Neg) => "neg",
+            Method::Unary(UnaryOps::Sqrt) => "sqrt",
+            Method::Unary(UnaryOps::Round) => "round",
+            Method::Unary(UnaryOps::Floor) => "floor",
+            Method::Unary(UnaryOps::Ceil) => "ceil",
+            Method::Unary(UnaryOps::Exp) => "exp",
+            Method::Unary(UnaryOps::Ln) => "ln",
+            Method::Unary(UnaryOps::Log) => "log",
+            Method::Unary(UnaryOps::Sin) => "sin",
+            Method::Unary(UnaryOps::Cos) => "cos",
+            Method::Unary(UnaryOps::Atan) => "atan",
+            Method::Compare(CompareOps::Compare) => "compare",
+            Method::Compare(CompareOps::Eq) => "eq",
+            Method::Compare(CompareOps::Lt) => "lt",
+            Method::Compare(CompareOps::Gt) => "gt",
+            Method::Predicate(PredicateOps::IsZero) => "is_zero",
+            Method::Predicate(PredicateOps::IsNegative) => "is_negative",
+            Method::Assign(BinaryOps::Add) => "add_assign",
+            Method::Assign(BinaryOps::Subtract) => "subtract_assign",
+            Method::Assign(BinaryOps::Multiply) => "multiply_assign",
+            Method::Assign(BinaryOps::Divide) => "divide_assign",
+            Method::Assign(BinaryOps::Pow) => "pow_assign",
+            Method::Assign(BinaryOps::Modulo) => "modulo_assign",
+            Method::Assign(BinaryOps::Min) => "min_assign",
+            Method::Assign(BinaryOps::Max) => "max_assign",
+            Method::Increment => "increment",
+            Method::Decrement => "decrement",
+            Method::Cas => "cas",
+            Method::Eval => "eval",
+            Method::Define => "define",
+            Method::List => "list",
+            Method::ScanPrefix => "scan_prefix",
+            Method::Range => "range",
+            Method::Aggregate(AggregateOps::Sum) => "sum",
+            Method::Aggregate(AggregateOps::Product) => "product",
+            Method::Aggregate(AggregateOps::Mean) => "mean",
+            Method::Aggregate(AggregateOps::Min) => "minimum",
+            Method::Aggregate(AggregateOps::Max) => "maximum",
+            Method::Aggregate(AggregateOps::Count) => "count",
+            Method::Aggregate(AggregateOps::Variance) => "variance",
+            Method::MultiRead => "mread",
+            Method::MultiCreate => "mcreate",
+            Method::MultiUpdate => "mupdate",
         };
 
         str_slice.to_string()
     }
 }
 
-impl From<String> for Method {
-    fn from(value: String) -> Self {
-        match value.as_str() {
+impl FromStr for Method {
+    type Err = ServerError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let method = match value {
             "create" => Method::Create,
             "read" => Method::Read,
             "update" => Method::Update,
+            "set" | "upsert" => Method::Set,
             "delete" => Method::Delete,
             "add" => Method::Binary(BinaryOps::Add),
             "subtract" => Method::Binary(BinaryOps::Subtract),
             "multiply" => Method::Binary(BinaryOps::Multiply),
             "divide" => Method::Binary(BinaryOps::Divide),
-            _ => unreachable!(),
-        }
+            "pow" => Method::Binary(BinaryOps::Pow),
+            "modulo" => Method::Binary(BinaryOps::Modulo),
+            "min" => Method::Binary(BinaryOps::Min),
+            "max" => Method::Binary(BinaryOps::Max),
+            "abs" => Method::Unary(UnaryOps::Abs),
+            "neg" => Method::Unary(UnaryOps::Neg),
+            "sqrt" => Method::Unary(UnaryOps::Sqrt),
+            "round" => Method::Unary(UnaryOps::Round),
+            "floor" => Method::Unary(UnaryOps::Floor),
+            "ceil" => Method::Unary(UnaryOps::Ceil),
+            "exp" => Method::Unary(UnaryOps::Exp),
+            "ln" => Method::Unary(UnaryOps::Ln),
+            "log" => Method::Unary(UnaryOps::Log),
+            "sin" => Method::Unary(UnaryOps::Sin),
+            "cos" => Method::Unary(UnaryOps::Cos),
+            "atan" => Method::Unary(UnaryOps::Atan),
+            "compare" => Method::Compare(CompareOps::Compare),
+            "eq" => Method::Compare(CompareOps::Eq),
+            "lt" => Method::Compare(CompareOps::Lt),
+            "gt" => Method::Compare(CompareOps::Gt),
+            "is_zero" => Method::Predicate(PredicateOps::IsZero),
+            "is_negative" => Method::Predicate(PredicateOps::IsNegative),
+            "add_assign" => Method::Assign(BinaryOps::Add),
+            "subtract_assign" => Method::Assign(BinaryOps::Subtract),
+            "multiply_assign" => Method::Assign(BinaryOps::Multiply),
+            "divide_assign" => Method::Assign(BinaryOps::Divide),
+            "pow_assign" => Method::Assign(BinaryOps::Pow),
+            "modulo_assign" => Method::Assign(BinaryOps::Modulo),
+            "min_assign" => Method::Assign(BinaryOps::Min),
+            "max_assign" => Method::Assign(BinaryOps::Max),
+            "increment" => Method::Increment,
+            "decrement" => Method::Decrement,
+            "cas" => Method::Cas,
+            "eval" => Method::Eval,
+            "define" => Method::Define,
+            "list" => Method::List,
+            "scan_prefix" => Method::ScanPrefix,
+            "range" => Method::Range,
+            "sum" => Method::Aggregate(AggregateOps::Sum),
+            "product" => Method::Aggregate(AggregateOps::Product),
+            "mean" => Method::Aggregate(AggregateOps::Mean),
+            "minimum" => Method::Aggregate(AggregateOps::Min),
+            "maximum" => Method::Aggregate(AggregateOps::Max),
+            "count" => Method::Aggregate(AggregateOps::Count),
+            "variance" => Method::Aggregate(AggregateOps::Variance),
+            "mread" => Method::MultiRead,
+            "mcreate" => Method::MultiCreate,
+            "mupdate" => Method::MultiUpdate,
+            unknown => return Err(ServerError::MethodNotFound(unknown.into())),
+        };
+
+        Ok(method)
+    }
+}
+
+impl TryFrom<String> for Method {
+    type Error = ServerError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
     }
 }
 
@@ -255,11 +899,68 @@ impl fmt::Display for Method {
             Method::Create => write!(f, "create"),
             Method::Read => write!(f, "read"),
             Method::Update => write!(f, "update"),
+            Method::Set => write!(f, "set"),
             Method::Delete => write!(f, "delete"),
             Method::Binary(BinaryOps::Add) => write!(f, "add"),
             Method::Binary(BinaryOps::Subtract) => write!(f, "subtract"),
             Method::Binary(BinaryOps::Multiply) => write!(f, "multiply"),
             Method::Binary(BinaryOps::Divide) => write!(f, "divide"),
+            Method::Binary(BinaryOps::Pow) => write!(f, "pow"),
+            Method::Binary(BinaryOps::Modulo) => write!(f, "modulo"),
+            Method::Binary(BinaryOps::Min) => write!(f, "min"),
+            Method::Binary(BinaryOps::Max) => write!(f, "max"),
+            Method::Unary(UnaryOps::Abs) => write!(f, "abs"),
+            Method::Unary(UnaryOps::Neg) => write!(f, "neg"),
+            Method::Unary(UnaryOps::Sqrt) => write!(f, "sqrt"),
+            Method::Unary(UnaryOps::Round) => write!(f, "round"),
+            Method::Unary(UnaryOps::Floor) => write!(f, "floor"),
+            Method::Unary(UnaryOps::Ceil) => write!(f, "ceil"),
+            Method::Unary(UnaryOps::Exp) => write!(f, "exp"),
+            Method::Unary(UnaryOps::Ln) => write!(f, "ln"),
+            Method::Unary(UnaryOps::Log) => write!(f, "log"),
+            Method::Unary(UnaryOps::Sin) => write!(f, "sin"),
+            Method::Unary(UnaryOps::Cos) => write!(f, "cos"),
+            Method::Unary(UnaryOps::Atan) => write!(f, "atan"),
+            Method::Compare(CompareOps::Compare) => write!(f, "compare"),
+            Method::Compare(CompareOps::Eq) => write!(f, "eq"),
+            Method::Compare(CompareOps::Lt) => write!(f, "lt"),
+            Method::Compare(CompareOps::Gt) => write!(f, "gt"),
+            Method::Predicate(PredicateOps::IsZero) => write!(f, "is_zero"),
+            Method::Predicate(PredicateOps::IsNegative) => write!(f, "is_negative"),
+            Method::Assign(BinaryOps::Add) => write!(f, "add_assign"),
+            Method::Assign(BinaryOps::Subtract) => write!(f, "subtract_assign"),
+            Method::Assign(BinaryOps::Multiply) => write!(f, "multiply_assign"),
+            Method::Assign(BinaryOps::Divide) => write!(f, "divide_assign"),
+            Method::Assign(BinaryOps::Pow) => write!(f, "pow_assign"),
+            Method::Assign(BinaryOps::Modulo) => write!(f, "modulo_assign"),
+            Method::Assign(BinaryOps::Min) => write!(f, "min_assign"),
+            Method::Assign(BinaryOps::Max) => write!(f, "max_assign"),
+            Method::Increment => write!(f, "increment"),
+            Method::Decrement => write!(f, "decrement"),
+            Method::Cas => write!(f, "cas"),
+            Method::Eval => write!(f, "eval"),
+            Method::Define => write!(f, "define"),
+            Method::List => write!(f, "list"),
+            Method::ScanPrefix => write!(f, "scan_prefix"),
+            Method::Range => write!(f, "range"),
+            Method::Aggregate(ref op) => write!(f, "{op}"),
+            Method::MultiRead => write!(f, "mread"),
+            Method::MultiCreate => write!(f, "mcreate"),
+            Method::MultiUpdate => write!(f, "mupdate"),
+        }
+    }
+}
+
+impl fmt::Display for AggregateOps {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            AggregateOps::Sum => write!(f, "sum"),
+            AggregateOps::Product => write!(f, "product"),
+            AggregateOps::Mean => write!(f, "mean"),
+            AggregateOps::Min => write!(f, "minimum"),
+            AggregateOps::Max => write!(f, "maximum"),
+            AggregateOps::Count => write!(f, "count"),
+            AggregateOps::Variance => write!(f, "variance"),
         }
     }
 }
@@ -271,6 +972,165 @@ impl fmt::Display for BinaryOps {
             BinaryOps::Subtract => write!(f, "subtract"),
             BinaryOps::Multiply => write!(f, "multiply"),
             BinaryOps::Divide => write!(f, "divide"),
+            BinaryOps::Pow => write!(f, "pow"),
+            BinaryOps::Modulo => write!(f, "modulo"),
+            BinaryOps::Min => write!(f, "min"),
+            BinaryOps::Max => write!(f, "max"),
+        }
+    }
+}
+
+impl fmt::Display for UnaryOps {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            UnaryOps::Abs => write!(f, "abs"),
+            UnaryOps::Neg => write!(f, "neg"),
+            UnaryOps::Sqrt => write!(f, "sqrt"),
+            UnaryOps::Round => write!(f, "round"),
+            UnaryOps::Floor => write!(f, "floor"),
+            UnaryOps::Ceil => write!(f, "ceil"),
+            UnaryOps::Exp => write!(f, "exp"),
+            UnaryOps::Ln => write!(f, "ln"),
+            UnaryOps::Log => write!(f, "log"),
+            UnaryOps::Sin => write!(f, "sin"),
+            UnaryOps::Cos => write!(f, "cos"),
+            UnaryOps::Atan => write!(f, "atan"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn option_params() {
+        for name in OPTION_NAMES {
+            let literal = format!("{name}=1");
+            assert!(
+                matches!(Param::from(literal.clone()), Param::Option { name: n, value } if &*n == name && &*value == "1"),
+                "{literal}"
+            );
+        }
+        for literal in ["a=b", "=1", "x==y", "precision"] {
+            assert!(
+                matches!(Param::from(literal.to_string()), Param::Name(name) if &*name == literal),
+                "{literal}"
+            );
+        }
+        assert!(matches!(Param::from("-1.5".to_string()), Param::Number(_)));
+    }
+
+    #[test]
+    fn json_number_params() {
+        use crate::prelude::v2::{Id, ReqBody};
+
+        let req_body: ReqBody = serde_json::from_str(
+            r#"{"jsonrpc":"2.0","method":"add","params":["x",18446744073709551615,-3],"id":18446744073709551615}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            req_body.id,
+            Some(Id::Number(serde_json::Number::from(u64::MAX)))
+        );
+        let params = req_body.parse_params().unwrap();
+        assert!(
+            matches!(&params[1], Param::Number(number) if number.to_string() == "18446744073709551615")
+        );
+        assert!(matches!(&params[2], Param::Number(number) if number.to_string() == "-3"));
+
+        let req_body: ReqBody = serde_json::from_str(
+            r#"{"jsonrpc":"2.0","method":"add","params":["x",0.12345678901234567890123],"id":1}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            req_body.parse_params(),
+            Err(ServerError::InexactNumber(1))
+        ));
+    }
+
+    #[test]
+    fn v1_response_build() {
+        use crate::error::{ErrorCode, ErrorObject};
+        use crate::prelude::v1::{RespBody, ResponseBuilder};
+
+        let payload = ResponseBuilder::new(serde_json::json!({"cursor": null}), 7).build();
+        let body: RespBody = crate::frame::decode(&payload).unwrap();
+        assert_eq!(body.result, Some(serde_json::json!({"cursor": null})));
+        assert_eq!(body.id, 7);
+
+        let error = ErrorObject::new(ErrorCode::Timeout, "server timeout.".to_string());
+        let payload = ResponseBuilder::error(error.clone(), 8).build();
+        let body: RespBody = crate::frame::decode(&payload).unwrap();
+        assert_eq!((body.result, body.error, body.id), (None, Some(error), 8));
+    }
+
+    mod dispatch {
+        use crate::database::ConnectionPool;
+        use crate::prelude::v2::{dispatch, UserDatabase};
+
+        use serde_json::{json, Value};
+
+        /// open a user database in a fresh directory named by `name`.
+        fn database(name: &str) -> UserDatabase {
+            let path = std::env::temp_dir()
+                .join(format!("acrudjson-dispatch-{name}-{}", std::process::id()));
+            let _ = std::fs::remove_dir_all(&path);
+            ConnectionPool::init(path)
+                .and_then(|pool| pool.open_user_database(&b"test"[..]))
+                .unwrap()
+        }
+
+        fn reply(database: &UserDatabase, json: &str) -> Option<Value> {
+            dispatch(database, json.as_bytes())
+                .map(|response| crate::frame::decode(&response.build()).unwrap())
+        }
+
+        #[test]
+        fn notifications_are_not_replied() {
+            let database = database("notifications");
+            let create = r#"{"jsonrpc":"2.0","method":"create","params":["a","1"]}"#;
+            assert_eq!(reply(&database, create), None);
+            let batch = r#"[
+                {"jsonrpc":"2.0","method":"increment","params":["a"]},
+                {"jsonrpc":"2.0","method":"read","params":["a"],"id":1},
+                {"jsonrpc":"2.0","method":"unknown"}
+            ]"#;
+            assert_eq!(
+                reply(&database, batch),
+                Some(json!([{"jsonrpc": "2.0", "result": "2", "id": 1}]))
+            );
+            let notifications = r#"[
+                {"jsonrpc":"2.0","method":"increment","params":["a"]},
+                {"jsonrpc":"2.0","method":"read","params":["a"]}
+            ]"#;
+            assert_eq!(reply(&database, notifications), None);
+        }
+
+        #[test]
+        fn invalid_batches() {
+            let database = database("batches");
+            let empty = reply(&database, "[]").unwrap();
+            assert_eq!(empty["error"]["code"], -32600);
+            assert_eq!(empty["id"], Value::Null);
+
+            let batch = r#"[1, {"jsonrpc":"2.0","method":"add","params":["1","2"],"id":"x"}]"#;
+            let replies = reply(&database, batch).unwrap();
+            let replies = replies.as_array().unwrap();
+            assert_eq!(replies.len(), 2);
+            assert_eq!(replies[0]["error"]["code"], -32600);
+            assert_eq!(replies[0]["id"], Value::Null);
+            assert_eq!(replies[1]["id"], "x");
+        }
+
+        #[test]
+        fn parse_error() {
+            let database = database("parse");
+            for json in ["{", r#"[{"jsonrpc":"2.0","method":"read""#] {
+                let error = reply(&database, json).unwrap();
+                assert_eq!(error["error"]["code"], -32700, "{json}");
+                assert_eq!(error["id"], Value::Null);
+            }
         }
     }
 }
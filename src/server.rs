@@ -0,0 +1,571 @@
+use crate::database::ConnectionPool;
+use crate::error::ServerError;
+use crate::frame;
+use crate::prelude::{v1, v2};
+use crate::{JsonInternal, MathContext};
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use log::{error, info, warn};
+use serde_json::Value;
+use tokio::{net::UdpSocket, runtime::Builder, task, time::timeout};
+
+const DEFAULT_SERVER_PORT: u16 = 9999;
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_IDEMPOTENCY_TTL: Duration = Duration::from_secs(60);
+const DEFAULT_IDEMPOTENCY_CAPACITY: usize = 4096;
+const UDP_DATAGRAM_MAX_SIZE: usize = 65536;
+
+//TODO: validate UserToken
+const DEFAULT_USER_TOKEN: &[u8] = b"default";
+
+/// Used to configure and create [`UdpServer`].
+///
+/// [`UdpServer`]: crate::server::UdpServer
+pub struct UdpServerBuilder {
+    bind_addr: SocketAddr,
+    storage_path: PathBuf,
+    worker_threads: usize,
+    request_timeout: Duration,
+    idempotency_ttl: Duration,
+    idempotency_capacity: usize,
+    max_datagram_size: usize,
+    math_context: MathContext,
+}
+
+impl Default for UdpServerBuilder {
+    fn default() -> Self {
+        UdpServerBuilder {
+            bind_addr: SocketAddr::from((Ipv4Addr::UNSPECIFIED, DEFAULT_SERVER_PORT)),
+            storage_path: std::env::temp_dir().join("jsonrpc_storage"),
+            worker_threads: std::thread::available_parallelism()
+                .map(|cores| cores.get())
+                .unwrap_or(1),
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            idempotency_ttl: DEFAULT_IDEMPOTENCY_TTL,
+            idempotency_capacity: DEFAULT_IDEMPOTENCY_CAPACITY,
+            max_datagram_size: UDP_DATAGRAM_MAX_SIZE,
+            math_context: MathContext::default(),
+        }
+    }
+}
+
+impl UdpServerBuilder {
+    /// set the socket address the server listens on, default to `0.0.0.0:9999`.
+    pub fn bind_addr(mut self, addr: SocketAddr) -> Self {
+        self.bind_addr = addr;
+        self
+    }
+
+    /// set the filepath of `sled` database storage, default to `jsonrpc_storage` in temporary
+    /// directory.
+    pub fn storage_path(mut self, path: impl AsRef<Path>) -> Self {
+        self.storage_path = path.as_ref().to_path_buf();
+        self
+    }
+
+    /// set the number of worker threads of `tokio` runtime started by [`UdpServer::run`],
+    /// default to the number of available CPU cores.
+    ///
+    /// [`UdpServer::run`]: crate::server::UdpServer::run
+    pub fn worker_threads(mut self, count: usize) -> Self {
+        self.worker_threads = count.max(1);
+        self
+    }
+
+    /// set the duration to wait for a request being processed before replying with timeout
+    /// error, default to 5 seconds.
+    pub fn request_timeout(mut self, duration: Duration) -> Self {
+        self.request_timeout = duration;
+        self
+    }
+
+    /// set the duration to keep responses for replaying retried requests, default to 60
+    /// seconds. See [`UdpServer`] for idempotency of retried requests.
+    ///
+    /// [`UdpServer`]: crate::server::UdpServer
+    pub fn idempotency_ttl(mut self, duration: Duration) -> Self {
+        self.idempotency_ttl = duration;
+        self
+    }
+
+    /// set the maximum number of requests kept for replaying, default to 4096. The oldest
+    /// response is evicted to admit a new request once the capacity is reached.
+    pub fn idempotency_capacity(mut self, count: usize) -> Self {
+        self.idempotency_capacity = count.max(1);
+        self
+    }
+
+    /// set the size of receiving buffer, datagrams larger than it are truncated, default to
+    /// 65536 bytes.
+    pub fn max_datagram_size(mut self, size: usize) -> Self {
+        self.max_datagram_size = size;
+        self
+    }
+
+    /// set the default [`MathContext`] of user databases, default to exact results without
+    /// rounding.
+    ///
+    /// [`MathContext`]: crate::MathContext
+    pub fn math_context(mut self, context: MathContext) -> Self {
+        self.math_context = context;
+        self
+    }
+
+    /// open the connection pool of storage and create [`UdpServer`].
+    ///
+    /// [`UdpServer`]: crate::server::UdpServer
+    pub fn build(self) -> Result<UdpServer, ServerError> {
+        let pool = ConnectionPool::init(&self.storage_path)?;
+
+        Ok(UdpServer {
+            cache: Arc::new(IdempotencyCache::new(
+                self.idempotency_ttl,
+                self.idempotency_capacity,
+            )),
+            config: self,
+            pool: Arc::new(pool),
+        })
+    }
+}
+
+/// An asynchronous UDP server which receives JSON-RPC 1.0 and 2.0 requests framed by
+/// `RequestBuilder::build`, then dispatches them to the user database.
+///
+/// NOTE:
+///     - each datagram is verified by the crc32 checksum in tail bytes, unrecognisable
+///     datagrams are dropped.
+///     - requests are processed on blocking threads of `tokio` runtime, a timeout error is
+///     replied if a request is not completed in time.
+///     - requests are idempotent by peer address, JSON request `id` and request body, a retried
+///     request is not applied twice but replied with the response of the original one. The
+///     retried request is dropped if the original one is still in process. A different request
+///     reusing the same `id` is processed as a new one.
+///     - at most `idempotency_capacity` requests are kept, the oldest response is evicted for a
+///     new request, or the new request is processed without idempotency if all of them are
+///     still in process.
+pub struct UdpServer {
+    config: UdpServerBuilder,
+    pool: Arc<ConnectionPool>,
+    cache: Arc<IdempotencyCache>,
+}
+
+impl UdpServer {
+    /// creates [`UdpServerBuilder`] with default configuration.
+    ///
+    /// [`UdpServerBuilder`]: crate::server::UdpServerBuilder
+    pub fn builder() -> UdpServerBuilder {
+        UdpServerBuilder::default()
+    }
+
+    /// start a multi-thread `tokio` runtime with configured worker threads, then block the
+    /// current thread on [`UdpServer::serve`].
+    ///
+    /// [`UdpServer::serve`]: crate::server::UdpServer::serve
+    pub fn run(self) -> Result<(), ServerError> {
+        let rt = Builder::new_multi_thread()
+            .worker_threads(self.config.worker_threads)
+            .enable_all()
+            .build()?;
+
+        rt.block_on(self.serve())
+    }
+
+    /// bind the UDP socket and run the receive loop in the current `tokio` runtime.
+    pub async fn serve(self) -> Result<(), ServerError> {
+        let socket = Arc::new(UdpSocket::bind(self.config.bind_addr).await?);
+        info!("UDP server running on {}", socket.local_addr()?);
+        let mut datagram_buf = vec![0_u8; self.config.max_datagram_size];
+        loop {
+            let (len, peer) = socket.recv_from(&mut datagram_buf).await?;
+            info!("receiving UDP datagram from {peer}");
+            let payload = datagram_buf[..len].to_vec();
+            let key = match idempotency_key(peer, &payload) {
+                Some(key) => match self.cache.begin(&key) {
+                    Lookup::New => Some(key),
+                    Lookup::Untracked => {
+                        warn!("idempotency cache is full, process request from peer {peer} without it.");
+                        None
+                    }
+                    Lookup::InFlight => {
+                        warn!("drop retried request from peer {peer}, it is still in process.");
+                        continue;
+                    }
+                    Lookup::Replay(resp_payload) => {
+                        info!("replay response of retried request to peer {peer}");
+                        if let Some(resp_payload) = resp_payload {
+                            if let Err(e) = socket.send_to(&resp_payload, peer).await {
+                                error!("failed to send response to peer {peer}, reason: {e}");
+                            }
+                        }
+                        continue;
+                    }
+                },
+                None => None,
+            };
+            let pool = self.pool.clone();
+            let cache = self.cache.clone();
+            let send_sock = socket.clone();
+            let ttl = self.config.request_timeout;
+            let context = self.config.math_context;
+            tokio::spawn(async move {
+                let resp_payload = match timeout(
+                    ttl,
+                    task::spawn_blocking({
+                        let payload = payload.clone();
+                        move || {
+                            let guard = key.map(|key| InFlightGuard {
+                                cache,
+                                key: Some(key),
+                            });
+                            let resp_payload = process(&pool, &payload, context);
+                            // record the response even if the request timed out, since it has
+                            // been applied to user database.
+                            if let Some(guard) = guard {
+                                guard.complete(resp_payload.clone());
+                            }
+                            resp_payload
+                        }
+                    }),
+                )
+                .await
+                {
+                    Ok(Ok(resp_payload)) => resp_payload,
+                    Ok(Err(e)) => {
+                        error!("failed to process request from peer {peer}, reason: {e}");
+                        return;
+                    }
+                    // composing JSON response on database connection timeout.
+                    Err(_) => timeout_response(&payload),
+                };
+
+                // nothing to reply for notifications or unrecognisable datagrams.
+                if let Some(resp_payload) = resp_payload {
+                    match send_sock.send_to(&resp_payload, peer).await {
+                        Ok(_) => info!("response has been successfully sent to peer {peer}"),
+                        Err(e) => error!("failed to send response to peer {peer}, reason: {e}"),
+                    }
+                }
+            });
+        }
+    }
+}
+
+/// The key identifying a request from peer, composed by peer address, the JSON text of request
+/// `id` members and the hash of request body.
+type IdempotencyKey = (SocketAddr, String, u64);
+
+/// The state of request looked up in [`IdempotencyCache`].
+enum Lookup {
+    /// the request is seen for the first time.
+    New,
+    /// the request is seen for the first time but not tracked, the cache is full of requests
+    /// in process.
+    Untracked,
+    /// the original request is still in process.
+    InFlight,
+    /// the original request has been processed with the response payload.
+    Replay(Option<Vec<u8>>),
+}
+
+enum CacheEntry {
+    InFlight,
+    Done {
+        resp_payload: Option<Vec<u8>>,
+        expire_at: Instant,
+    },
+}
+
+/// The cache of processed requests to replay their responses to retried requests.
+struct IdempotencyCache {
+    ttl: Duration,
+    capacity: usize,
+    entries: Mutex<HashMap<IdempotencyKey, CacheEntry>>,
+    last_purge: Mutex<Instant>,
+}
+
+impl IdempotencyCache {
+    fn new(ttl: Duration, capacity: usize) -> Self {
+        IdempotencyCache {
+            ttl,
+            capacity,
+            entries: Mutex::new(HashMap::new()),
+            last_purge: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// look up the request by `key`, and mark it in process if it's new.
+    ///
+    /// NOTE: the response expiring first is evicted if the cache is full, the request is left
+    /// untracked if all cached requests are still in process.
+    fn begin(&self, key: &IdempotencyKey) -> Lookup {
+        self.purge();
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some(CacheEntry::InFlight) => return Lookup::InFlight,
+            Some(CacheEntry::Done {
+                resp_payload,
+                expire_at,
+            }) if *expire_at > Instant::now() => return Lookup::Replay(resp_payload.clone()),
+            Some(CacheEntry::Done { .. }) => {
+                entries.insert(key.clone(), CacheEntry::InFlight);
+                return Lookup::New;
+            }
+            None => (),
+        }
+
+        if entries.len() >= self.capacity {
+            let oldest = entries
+                .iter()
+                .filter_map(|(key, entry)| match entry {
+                    CacheEntry::InFlight => None,
+                    CacheEntry::Done { expire_at, .. } => Some((expire_at, key)),
+                })
+                .min_by_key(|(expire_at, _)| *expire_at)
+                .map(|(_, key)| key.clone());
+            match oldest {
+                Some(oldest) => entries.remove(&oldest),
+                None => return Lookup::Untracked,
+            };
+        }
+        entries.insert(key.clone(), CacheEntry::InFlight);
+        Lookup::New
+    }
+
+    /// record the response payload of processed request, which has been marked in process by
+    /// [`IdempotencyCache::begin`].
+    fn complete(&self, key: IdempotencyKey, resp_payload: Option<Vec<u8>>) {
+        if let Some(entry) = self.entries.lock().unwrap().get_mut(&key) {
+            *entry = CacheEntry::Done {
+                resp_payload,
+                expire_at: Instant::now() + self.ttl,
+            };
+        }
+    }
+
+    /// remove expired responses at most once per `ttl`.
+    fn purge(&self) {
+        let mut last_purge = self.last_purge.lock().unwrap();
+        if last_purge.elapsed() < self.ttl {
+            return;
+        }
+        let now = Instant::now();
+        self.entries.lock().unwrap().retain(|_, entry| match entry {
+            CacheEntry::InFlight => true,
+            CacheEntry::Done { expire_at, .. } => *expire_at > now,
+        });
+        *last_purge = now;
+    }
+}
+
+/// Release the in-process mark of request if it's not completed, e.g. the processing panicked,
+/// so that it can be retried.
+struct InFlightGuard {
+    cache: Arc<IdempotencyCache>,
+    key: Option<IdempotencyKey>,
+}
+
+impl InFlightGuard {
+    fn complete(mut self, resp_payload: Option<Vec<u8>>) {
+        if let Some(key) = self.key.take() {
+            self.cache.complete(key, resp_payload);
+        }
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.cache.entries.lock().unwrap().remove(&key);
+        }
+    }
+}
+
+/// compose the idempotency key from `id` members and body of the single or batch request,
+/// return `None` for notifications and unrecognisable datagrams.
+fn idempotency_key(peer: SocketAddr, payload: &[u8]) -> Option<IdempotencyKey> {
+    let body = frame::verify(payload).ok()?;
+    let id = match serde_json::from_slice::<Value>(body).ok()? {
+        Value::Array(batch) => {
+            let ids: Vec<Value> = batch
+                .into_iter()
+                .filter_map(|mut request| request.get_mut("id").map(Value::take))
+                .collect();
+            if ids.is_empty() {
+                return None;
+            }
+            Value::Array(ids)
+        }
+        mut request => request.get_mut("id").map(Value::take)?,
+    };
+
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+
+    Some((peer, id.to_string(), hasher.finish()))
+}
+
+/// verify the datagram payload then dispatch the request body by JSON-RPC version on user
+/// database with default `context`, return the response payload to reply.
+fn process(pool: &ConnectionPool, payload: &[u8], context: MathContext) -> Option<Vec<u8>> {
+    let body = verify(payload)?;
+    let database = match pool.open_user_database(DEFAULT_USER_TOKEN) {
+        Ok(database) => database.with_math_context(context),
+        Err(e) => {
+            error!("failed to open user database, reason: {e}");
+            return None;
+        }
+    };
+
+    if is_jsonrpc_v2(body) {
+        return v2::dispatch(&database, body).map(|resp| resp.build());
+    }
+
+    let req_body: v1::ReqBody = match serde_json::from_slice(body) {
+        Ok(req_body) => req_body,
+        Err(e) => {
+            error!("failed to parse JSON request body, reason: {e}");
+            return None;
+        }
+    };
+    let resp = match req_body
+        .parse_method()
+        .and_then(|method| database.transaction(method, req_body.parse_params()?))
+    {
+        Ok(Some(res)) => v1::ResponseBuilder::new(res, req_body.id),
+        Ok(None) => v1::ResponseBuilder::success(req_body.id),
+        Err(e) => v1::ResponseBuilder::error(e.into(), req_body.id),
+    };
+
+    Some(resp.build())
+}
+
+/// compose timeout error response to the request carried by `payload`.
+fn timeout_response(payload: &[u8]) -> Option<Vec<u8>> {
+    let body = verify(payload)?;
+    if is_jsonrpc_v2(body) {
+        // batch array and notification are not replied on timeout.
+        let req_body: v2::ReqBody = serde_json::from_slice(body).ok()?;
+        let resp = v2::ResponseBuilder::error(ServerError::Timeout.into(), req_body.id?);
+        Some(resp.build())
+    } else {
+        let req_body: v1::ReqBody = serde_json::from_slice(body).ok()?;
+        let resp = v1::ResponseBuilder::error(ServerError::Timeout.into(), req_body.id);
+        Some(resp.build())
+    }
+}
+
+/// verify the crc32 checksum of datagram payload, return the JSON request body.
+fn verify(payload: &[u8]) -> Option<&[u8]> {
+    match frame::verify(payload) {
+        Ok(body) => Some(body),
+        Err(e) => {
+            error!("unrecognisable payload from receiving UDP datagram, reason: {e}");
+            None
+        }
+    }
+}
+
+fn is_jsonrpc_v2(body: &[u8]) -> bool {
+    match serde_json::from_slice::<Value>(body) {
+        // batch array is only supported by JSON-RPC 2.0
+        Ok(Value::Array(_)) => true,
+        Ok(value) => value["jsonrpc"] == "2.0",
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn key(id: u64) -> IdempotencyKey {
+        (
+            SocketAddr::from((Ipv4Addr::LOCALHOST, 9999)),
+            id.to_string(),
+            0,
+        )
+    }
+
+    #[test]
+    fn replay_completed_requests() {
+        let cache = IdempotencyCache::new(Duration::from_millis(100), 16);
+        assert!(matches!(cache.begin(&key(1)), Lookup::New));
+        assert!(matches!(cache.begin(&key(1)), Lookup::InFlight));
+
+        cache.complete(key(1), Some(b"response".to_vec()));
+        match cache.begin(&key(1)) {
+            Lookup::Replay(resp_payload) => {
+                assert_eq!(resp_payload.as_deref(), Some(&b"response"[..]))
+            }
+            _ => panic!("completed request is not replayed"),
+        }
+        cache.complete(key(2), None);
+        assert!(
+            matches!(cache.begin(&key(2)), Lookup::New),
+            "untracked request is cached"
+        );
+
+        thread::sleep(Duration::from_millis(150));
+        assert!(
+            matches!(cache.begin(&key(1)), Lookup::New),
+            "expired response is replayed"
+        );
+        assert_eq!(cache.entries.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn evict_oldest_response_at_capacity() {
+        let cache = IdempotencyCache::new(Duration::from_secs(60), 2);
+        for id in 1..=2 {
+            assert!(matches!(cache.begin(&key(id)), Lookup::New));
+            cache.complete(key(id), None);
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        assert!(matches!(cache.begin(&key(3)), Lookup::New));
+        assert!(matches!(cache.begin(&key(2)), Lookup::Replay(None)));
+        assert!(
+            matches!(cache.begin(&key(1)), Lookup::New),
+            "oldest response is not evicted"
+        );
+
+        // both entries are in process now, nothing can be evicted.
+        assert!(matches!(cache.begin(&key(4)), Lookup::Untracked));
+        assert_eq!(cache.entries.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn release_request_on_panic() {
+        let cache = Arc::new(IdempotencyCache::new(Duration::from_secs(60), 16));
+        assert!(matches!(cache.begin(&key(1)), Lookup::New));
+        let guard = InFlightGuard {
+            cache: cache.clone(),
+            key: Some(key(1)),
+        };
+        let result = thread::spawn(move || {
+            let _guard = guard;
+            panic!("processing panicked");
+        })
+        .join();
+
+        assert!(result.is_err());
+        assert!(
+            matches!(cache.begin(&key(1)), Lookup::New),
+            "request is still in process"
+        );
+        InFlightGuard {
+            cache: cache.clone(),
+            key: Some(key(1)),
+        }
+        .complete(None);
+        assert!(matches!(cache.begin(&key(1)), Lookup::Replay(None)));
+    }
+}